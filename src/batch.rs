@@ -0,0 +1,227 @@
+use glob;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use cli::Args;
+use export::Headless;
+use support;
+use support::camera::ViewPreset;
+use support::json;
+use support::render::Renderer;
+use support::stats::duration_ms;
+
+/// The result of loading and rendering one file in the batch
+struct Entry {
+    path: PathBuf,
+    /// File name of the thumbnail within the output directory
+    thumbnail: Option<String>,
+    num_models: usize,
+    num_triangles: usize,
+    num_materials: usize,
+    load_time: Duration,
+    warnings: Vec<String>,
+    /// Set if the file failed to load
+    error: Option<String>,
+}
+
+/// Load every OBJ file in the directory or matching the glob pattern, render a thumbnail
+/// of each framed from the isometric view and write a summary of them to `report.json`
+/// and `index.html` in the output directory.
+pub fn run(args: &Args, input: &str, output_dir: &Path) {
+    let files = find_models(input);
+    if files.is_empty() {
+        panic!("No OBJ files found in {}", input);
+    }
+    fs::create_dir_all(output_dir).unwrap();
+
+    let mut headless = Headless::new(args);
+    // Built for the first scene that loads, then reused for the others
+    let mut renderer: Option<Renderer> = None;
+    let mut entries = Vec::with_capacity(files.len());
+    for (i, path) in files.into_iter().enumerate() {
+        println!("Rendering {}", path.display());
+        let entry = match support::load_wavefront(&headless.context, &path, &args.load_options) {
+            Ok(scene) => {
+                match renderer {
+                    Some(ref mut r) => headless.set_scene(r, &scene, &path),
+                    None => renderer = Some(headless.renderer(&scene, &path)),
+                }
+                let renderer = renderer.as_ref().unwrap();
+                let bounds = scene.world_bounds();
+                headless.camera.frame(&bounds, ViewPreset::Iso);
+                headless.camera.fit_clip_planes(&bounds);
+                let img = headless.render(renderer, &scene);
+
+                let mut warnings = scene.warnings.clone();
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let name = format!("{:04}_{}.png", i, stem);
+                let thumbnail = match img.save(output_dir.join(&name)) {
+                    Ok(_) => Some(name),
+                    Err(e) => {
+                        warnings.push(format!("Failed to save thumbnail: {}", e));
+                        None
+                    }
+                };
+                Entry {
+                    path,
+                    thumbnail,
+                    num_models: scene.models.len(),
+                    num_triangles: scene.num_triangles(),
+                    num_materials: scene.num_materials,
+                    load_time: scene.load_times.total(),
+                    warnings,
+                    error: None,
+                }
+            }
+            Err(e) => Entry {
+                path,
+                thumbnail: None,
+                num_models: 0,
+                num_triangles: 0,
+                num_materials: 0,
+                load_time: Duration::new(0, 0),
+                warnings: Vec::new(),
+                error: Some(format!("{}", e)),
+            },
+        };
+        entries.push(entry);
+    }
+
+    let json_path = output_dir.join("report.json");
+    if let Err(e) = write_json(&json_path, &entries) {
+        panic!("Failed to write {}: {}", json_path.display(), e);
+    }
+    let html_path = output_dir.join("index.html");
+    if let Err(e) = write_html(&html_path, &entries) {
+        panic!("Failed to write {}: {}", html_path.display(), e);
+    }
+    let failed = entries.iter().filter(|e| e.error.is_some()).count();
+    println!(
+        "Rendered {} files ({} failed), report written to {}",
+        entries.len(),
+        failed,
+        html_path.display()
+    );
+}
+
+/// Returns the OBJ files in the directory, or the files matching the glob pattern
+fn find_models(input: &str) -> Vec<PathBuf> {
+    let input_path = Path::new(input);
+    let mut files: Vec<PathBuf> = if input_path.is_dir() {
+        fs::read_dir(input_path)
+            .unwrap()
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.extension()
+                    .map(|e| e.eq_ignore_ascii_case("obj"))
+                    .unwrap_or(false)
+            })
+            .collect()
+    } else {
+        match glob::glob(input) {
+            Ok(paths) => paths.filter_map(|p| p.ok()).collect(),
+            Err(e) => panic!("Invalid glob pattern {}: {}", input, e),
+        }
+    };
+    files.retain(|p| p.is_file());
+    files.sort();
+    files
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_json(path: &Path, entries: &[Entry]) -> io::Result<()> {
+    let mut f = File::create(path)?;
+    writeln!(f, "[")?;
+    for (i, e) in entries.iter().enumerate() {
+        let warnings: Vec<_> = e.warnings.iter().map(|w| json::string(w)).collect();
+        writeln!(f, "  {{")?;
+        writeln!(
+            f,
+            "    \"file\": {},",
+            json::string(&e.path.to_string_lossy())
+        )?;
+        writeln!(
+            f,
+            "    \"thumbnail\": {},",
+            e.thumbnail
+                .as_ref()
+                .map(|t| json::string(t))
+                .unwrap_or_else(|| "null".to_owned())
+        )?;
+        writeln!(f, "    \"models\": {},", e.num_models)?;
+        writeln!(f, "    \"triangles\": {},", e.num_triangles)?;
+        writeln!(f, "    \"materials\": {},", e.num_materials)?;
+        writeln!(f, "    \"load_time_ms\": {:.3},", duration_ms(e.load_time))?;
+        writeln!(f, "    \"warnings\": [{}],", warnings.join(", "))?;
+        writeln!(
+            f,
+            "    \"error\": {}",
+            e.error
+                .as_ref()
+                .map(|t| json::string(t))
+                .unwrap_or_else(|| "null".to_owned())
+        )?;
+        let separator = if i + 1 < entries.len() { "," } else { "" };
+        writeln!(f, "  }}{}", separator)?;
+    }
+    writeln!(f, "]")
+}
+
+fn write_html(path: &Path, entries: &[Entry]) -> io::Result<()> {
+    let mut f = File::create(path)?;
+    writeln!(
+        f,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>tobj viewer report</title>"
+    )?;
+    writeln!(
+        f,
+        "<style>body {{ font-family: sans-serif; }} td, th {{ padding: 4px 8px; text-align: left; \
+         vertical-align: top; }} .error {{ color: #c00; }} .warning {{ color: #a60; }}</style>"
+    )?;
+    writeln!(f, "</head>\n<body>\n<table>")?;
+    writeln!(
+        f,
+        "<tr><th>Preview</th><th>File</th><th>Models</th><th>Triangles</th>\
+         <th>Materials</th><th>Load time (ms)</th><th>Warnings</th></tr>"
+    )?;
+    for e in entries {
+        let preview = match e.thumbnail {
+            Some(ref t) => format!("<img src=\"{}\" width=\"128\">", html_escape(t)),
+            None => String::new(),
+        };
+        let mut notes: Vec<_> = e
+            .warnings
+            .iter()
+            .map(|w| format!("<div class=\"warning\">{}</div>", html_escape(w)))
+            .collect();
+        if let Some(ref err) = e.error {
+            notes.insert(
+                0,
+                format!(
+                    "<div class=\"error\">Failed to load: {}</div>",
+                    html_escape(err)
+                ),
+            );
+        }
+        writeln!(
+            f,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td><td>{}</td></tr>",
+            preview,
+            html_escape(&e.path.to_string_lossy()),
+            e.num_models,
+            e.num_triangles,
+            e.num_materials,
+            duration_ms(e.load_time),
+            notes.join("")
+        )?;
+    }
+    writeln!(f, "</table>\n</body>\n</html>")
+}
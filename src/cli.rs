@@ -0,0 +1,181 @@
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use support::orientation::{Handedness, UpAxis};
+use support::LoadOptions;
+
+const USAGE: &str = "Usage: ./exe [options] model_file
+       ./exe --batch DIR|GLOB [--output DIR] [options]
+       ./exe --validate [--output FILE] [options] model_file
+       ./exe --measure [--output FILE] [options] model_file
+
+Options:
+    --up y|z                 Up axis of the model
+    --left-handed            Treat the model as left-handed
+    --flip-winding           Reverse the triangle winding order
+    --environment FILE       Equirectangular .hdr image to light the model with, also drawn
+                             as the background
+    --flythrough FILE        Camera path to play back, defaults to the model's .fly file
+    --export-frames DIR      Render every frame of the flythrough to numbered PNGs in DIR
+    --turntable N            Render N views orbiting the model, to a contact sheet if the
+                             output is a .png file, otherwise into the output directory
+    --batch DIR|GLOB         Render a thumbnail of every OBJ file in the directory or matching
+                             the glob and write an HTML and JSON report on them
+    --validate               Check the model for degenerate, duplicate and non-manifold geometry
+                             and write a JSON report, exits with status 1 if issues are found
+    --measure                Print the surface area, volume and centroid of each model as JSON,
+                             or write them to the output file
+    --output PATH            Output of the turntable, defaults to turntable.png, the directory
+                             for the batch report, defaults to batch_report, or the validation
+                             report, defaults to the model's .validation.json file
+    --size WxH               Size of exported images, defaults to 1280x720
+    --fps N                  Frame rate of exported flythroughs, defaults to 30";
+
+/// What the viewer should do with the model
+pub enum Mode {
+    /// Open a window to view the model
+    View,
+    /// Render every frame of the flythrough into the directory without opening a window
+    ExportFrames { output_dir: PathBuf },
+    /// Render views orbiting the model into a contact sheet or directory without opening a window
+    Turntable { steps: usize, output: PathBuf },
+    /// Render thumbnails and a report for every OBJ file matching the input without opening a window
+    Batch { input: String, output: PathBuf },
+    /// Check the model's geometry and write a JSON report of the issues found
+    Validate { output: PathBuf },
+    /// Measure the surface area, volume and centroid of the models, printing the JSON
+    /// results if no output file is given
+    Measure { output: Option<PathBuf> },
+}
+
+/// The command line arguments of the viewer
+pub struct Args {
+    pub mode: Mode,
+    pub model_file: String,
+    pub load_options: LoadOptions,
+    pub environment: Option<PathBuf>,
+    pub flythrough_file: Option<PathBuf>,
+    pub size: (u32, u32),
+    pub fps: f32,
+}
+
+pub fn parse() -> Args {
+    let mut parsed = Args {
+        mode: Mode::View,
+        model_file: String::new(),
+        load_options: LoadOptions::default(),
+        environment: None,
+        flythrough_file: None,
+        size: (1280, 720),
+        fps: 30.0,
+    };
+    let mut model_file = None;
+    let mut output = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--up" => {
+                parsed.load_options.orientation.up = match value(&mut args, &arg).as_str() {
+                    "y" | "Y" => UpAxis::Y,
+                    "z" | "Z" => UpAxis::Z,
+                    _ => panic!("--up expects y or z\n{}", USAGE),
+                }
+            }
+            "--left-handed" => parsed.load_options.orientation.handedness = Handedness::Left,
+            "--right-handed" => parsed.load_options.orientation.handedness = Handedness::Right,
+            "--flip-winding" => parsed.load_options.flip_winding = true,
+            "--environment" => parsed.environment = Some(PathBuf::from(value(&mut args, &arg))),
+            "--flythrough" => parsed.flythrough_file = Some(PathBuf::from(value(&mut args, &arg))),
+            "--export-frames" => {
+                parsed.mode = Mode::ExportFrames {
+                    output_dir: PathBuf::from(value(&mut args, &arg)),
+                }
+            }
+            "--turntable" => {
+                parsed.mode = Mode::Turntable {
+                    steps: parse_value(&value(&mut args, &arg), &arg),
+                    output: PathBuf::new(),
+                }
+            }
+            "--batch" => {
+                parsed.mode = Mode::Batch {
+                    input: value(&mut args, &arg),
+                    output: PathBuf::new(),
+                }
+            }
+            "--validate" => {
+                parsed.mode = Mode::Validate {
+                    output: PathBuf::new(),
+                }
+            }
+            "--measure" => parsed.mode = Mode::Measure { output: None },
+            "--output" => output = Some(PathBuf::from(value(&mut args, &arg))),
+            "--size" => parsed.size = parse_size(&value(&mut args, &arg)),
+            "--fps" => parsed.fps = parse_fps(&value(&mut args, &arg)),
+            _ if arg.starts_with("--") => panic!("Unknown option {}\n{}", arg, USAGE),
+            _ => model_file = Some(arg),
+        }
+    }
+    match parsed.mode {
+        Mode::Batch { .. } => {}
+        _ => parsed.model_file = model_file.expect(USAGE),
+    }
+    parsed.mode = match parsed.mode {
+        Mode::Turntable { steps, .. } => {
+            if steps == 0 {
+                panic!("--turntable expects at least one view\n{}", USAGE);
+            }
+            Mode::Turntable {
+                steps,
+                output: output.unwrap_or_else(|| PathBuf::from("turntable.png")),
+            }
+        }
+        Mode::Batch { input, .. } => Mode::Batch {
+            input,
+            output: output.unwrap_or_else(|| PathBuf::from("batch_report")),
+        },
+        Mode::Measure { .. } => Mode::Measure { output },
+        Mode::Validate { .. } => Mode::Validate {
+            output: output.unwrap_or_else(|| {
+                PathBuf::from(&parsed.model_file).with_extension("validation.json")
+            }),
+        },
+        mode => mode,
+    };
+    parsed
+}
+
+fn value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> String {
+    match args.next() {
+        Some(v) => v,
+        None => panic!("{} expects a value\n{}", option, USAGE),
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str, option: &str) -> T {
+    match T::from_str(value) {
+        Ok(v) => v,
+        Err(_) => panic!("Invalid value {} for {}\n{}", value, option, USAGE),
+    }
+}
+
+fn parse_fps(value: &str) -> f32 {
+    let fps: f32 = parse_value(value, "--fps");
+    if fps.is_finite() && fps > 0.0 {
+        fps
+    } else {
+        panic!("--fps expects a positive frame rate\n{}", USAGE)
+    }
+}
+
+fn parse_size(value: &str) -> (u32, u32) {
+    let mut dims = value.split('x');
+    match (dims.next(), dims.next(), dims.next()) {
+        (Some(w), Some(h), None) => match (parse_value(w, "--size"), parse_value(h, "--size")) {
+            (0, _) | (_, 0) => panic!("--size expects a non-zero width and height\n{}", USAGE),
+            size => size,
+        },
+        _ => panic!("--size expects WxH\n{}", USAGE),
+    }
+}
@@ -0,0 +1,165 @@
+use std::f32;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use glium::HeadlessRenderer;
+use image::RgbaImage;
+
+use cli::Args;
+use support::camera::CameraState;
+use support::capture;
+use support::environment::Environment;
+use support::flythrough::Flythrough;
+use support::lights::{self, Light};
+use support::render::Renderer;
+use support::Scene;
+
+/// Elevation of the camera above the model's center for turntables, in radians
+const TURNTABLE_ELEVATION: f32 = 20.0 * f32::consts::PI / 180.0;
+
+/// An OpenGL context and camera for rendering from the command line without a window
+pub struct Headless {
+    pub context: HeadlessRenderer,
+    pub camera: CameraState,
+    pub size: (u32, u32),
+    environment: Option<Rc<Environment>>,
+}
+
+impl Headless {
+    pub fn new(args: &Args) -> Headless {
+        let (width, height) = args.size;
+        let context = capture::headless_context(width, height).unwrap();
+        let mut camera = CameraState::new();
+        camera.set_aspect_ratio(width as f32 / height as f32);
+        let environment =
+            args.environment
+                .as_ref()
+                .map(|path| match Environment::load(&context, path) {
+                    Ok(e) => Rc::new(e),
+                    Err(e) => panic!("Failed to load environment {}: {}", path.display(), e),
+                });
+        Headless {
+            context,
+            camera,
+            size: args.size,
+            environment,
+        }
+    }
+
+    /// Returns a renderer for the scene, lit by the lights saved for the model and without
+    /// the on-screen axis gizmo
+    pub fn renderer(&self, scene: &Scene, model_path: &Path) -> Renderer {
+        let mut renderer = Renderer::new(&self.context, scene);
+        renderer.overlays.show_axes = false;
+        renderer.lights = load_lights(model_path);
+        if let Some(ref environment) = self.environment {
+            renderer.set_environment(environment.clone());
+        }
+        renderer
+    }
+
+    /// Switch the renderer to another scene, lit by the lights saved for its model
+    pub fn set_scene(&self, renderer: &mut Renderer, scene: &Scene, model_path: &Path) {
+        renderer.set_scene(&self.context, scene);
+        renderer.lights = load_lights(model_path);
+    }
+
+    /// Render the scene from the camera's current viewpoint
+    pub fn render(&self, renderer: &Renderer, scene: &Scene) -> RgbaImage {
+        let (width, height) = self.size;
+        capture::render_to_image(&self.context, width, height, |target| {
+            renderer.draw(target, &self.camera, scene)
+        })
+    }
+}
+
+/// Load the lights saved for the model, falling back to the default key light
+fn load_lights(model_path: &Path) -> Vec<Light> {
+    lights::load(model_path).unwrap_or_else(|e| {
+        println!("Failed to load lights: {}", e);
+        vec![Light::key()]
+    })
+}
+
+/// Render each frame of the flythrough headlessly and save them as numbered PNGs
+pub fn flythrough_frames(args: &Args, fly_path: &Path, output_dir: &Path) {
+    let flythrough = match Flythrough::load(fly_path) {
+        Ok(f) => f,
+        Err(e) => panic!("Failed to load flythrough {}: {}", fly_path.display(), e),
+    };
+    if flythrough.is_empty() {
+        panic!("Flythrough {} has no keyframes", fly_path.display());
+    }
+
+    let mut headless = Headless::new(args);
+    let scene = ::load_scene(
+        &headless.context,
+        Path::new(&args.model_file),
+        &args.load_options,
+    );
+    let renderer = headless.renderer(&scene, Path::new(&args.model_file));
+
+    fs::create_dir_all(output_dir).unwrap();
+    let num_frames = (flythrough.duration() * args.fps).floor() as usize + 1;
+    for i in 0..num_frames {
+        headless
+            .camera
+            .set_pose(&flythrough.sample(i as f32 / args.fps).unwrap());
+        headless.camera.fit_clip_planes(&scene.world_bounds());
+        let img = headless.render(&renderer, &scene);
+        let path = output_dir.join(format!("frame_{:05}.png", i));
+        if let Err(e) = img.save(&path) {
+            panic!("Failed to save {}: {}", path.display(), e);
+        }
+    }
+    println!("Exported {} frames to {}", num_frames, output_dir.display());
+}
+
+/// Orbit the camera around the model in `steps` evenly spaced views, framing its bounding
+/// box in each. If `output` is a PNG file the views are combined into a contact sheet,
+/// otherwise they're saved as numbered PNGs in the `output` directory.
+pub fn turntable(args: &Args, steps: usize, output: &Path) {
+    let mut headless = Headless::new(args);
+    let scene = ::load_scene(
+        &headless.context,
+        Path::new(&args.model_file),
+        &args.load_options,
+    );
+    let renderer = headless.renderer(&scene, Path::new(&args.model_file));
+
+    let bounds = scene.world_bounds();
+    let mut frames = Vec::with_capacity(steps);
+    for i in 0..steps {
+        let angle = 2.0 * f32::consts::PI * i as f32 / steps as f32;
+        let dir = (
+            -f32::sin(angle) * f32::cos(TURNTABLE_ELEVATION),
+            -f32::sin(TURNTABLE_ELEVATION),
+            -f32::cos(angle) * f32::cos(TURNTABLE_ELEVATION),
+        );
+        headless.camera.frame_along(&bounds, dir, (0.0, 1.0, 0.0));
+        headless.camera.fit_clip_planes(&bounds);
+        frames.push(headless.render(&renderer, &scene));
+    }
+
+    let is_sheet = output
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("png"))
+        .unwrap_or(false);
+    if is_sheet {
+        let sheet = capture::contact_sheet(&frames);
+        if let Err(e) = sheet.save(output) {
+            panic!("Failed to save {}: {}", output.display(), e);
+        }
+        println!("Saved {} view contact sheet to {}", steps, output.display());
+    } else {
+        fs::create_dir_all(output).unwrap();
+        for (i, img) in frames.iter().enumerate() {
+            let path = output.join(format!("turntable_{:03}.png", i));
+            if let Err(e) = img.save(&path) {
+                panic!("Failed to save {}: {}", path.display(), e);
+            }
+        }
+        println!("Exported {} views to {}", steps, output.display());
+    }
+}
@@ -1,186 +1,739 @@
 extern crate cgmath;
 #[macro_use]
 extern crate glium;
+extern crate glob;
+extern crate image;
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::time::Instant;
+
+use glium::backend::Facade;
+use glium::glutin;
+
+use support::bookmarks::Bookmarks;
+use support::camera::ViewPreset;
+use support::clipping::ClipPlanes;
+use support::edges::EdgeAdjacency;
+use support::environment::Environment;
+use support::explode::Explode;
+use support::flythrough::Flythrough;
+use support::lights::{self, Light, MAX_LIGHTS};
+use support::measure;
+use support::picking::Ruler;
+use support::render::Renderer;
+use support::stats;
+use support::validate;
+use support::{LoadOptions, Scene, MODEL_SCALE};
+
+mod batch;
+mod cli;
+mod export;
+mod support;
 
-use std::default::Default;
-use std::path::Path;
+/// Change in field of view for each press of the FOV keys, in radians
+const FOV_STEP: f32 = 5.0 * std::f32::consts::PI / 180.0;
+/// Factor the selected light's brightness changes by for each press of the light keys
+const LIGHT_STEP: f32 = 1.25;
 
-use glium::{glutin, Surface};
+fn main() {
+    let args = cli::parse();
+    match args.mode {
+        cli::Mode::View => view(&args),
+        cli::Mode::ExportFrames { ref output_dir } => {
+            export::flythrough_frames(&args, &flythrough_path(&args), output_dir)
+        }
+        cli::Mode::Turntable { steps, ref output } => export::turntable(&args, steps, output),
+        cli::Mode::Validate { ref output } => validate(&args, output),
+        cli::Mode::Measure { ref output } => measure(&args, output.as_ref().map(|p| p.as_path())),
+        cli::Mode::Batch {
+            ref input,
+            ref output,
+        } => batch::run(&args, input, output),
+    }
+}
 
-mod support;
+/// Load the model, exiting if it can't be loaded
+fn load_scene<F: Facade>(facade: &F, path: &Path, options: &LoadOptions) -> Scene {
+    match support::load_wavefront(facade, path, options) {
+        Ok(scene) => {
+            stats::print_report(&scene);
+            scene
+        }
+        Err(e) => panic!("Loading of {:?} failed due to {:?}", path, e),
+    }
+}
 
-// This code is essentially straight from the glium teapot example
-fn main() {
-    let model_file = match std::env::args().nth(1) {
-        Some(arg) => arg,
-        None => panic!("Usage: ./exe model_file"),
+/// Check the model's geometry without opening a window, writing the issues found to
+/// `output` and exiting with an error status if there are any
+fn validate(args: &cli::Args, output: &Path) {
+    let path = Path::new(&args.model_file);
+    let mut warnings = Vec::new();
+    let models = match support::load_obj(path, &mut warnings) {
+        Ok((models, _)) => models,
+        Err(e) => panic!("Loading of {:?} failed due to {:?}", path, e),
     };
+    for w in &warnings {
+        println!("Warning: {}", w);
+    }
+    let reports: Vec<_> = models
+        .iter()
+        .map(|m| {
+            let adjacency = EdgeAdjacency::new(&m.mesh.positions, &m.mesh.indices);
+            validate::check_mesh(&m.name, &m.mesh, &adjacency, &args.load_options.orientation)
+        })
+        .collect();
+    for r in &reports {
+        println!("Model {}: {}", r.name, r.summary());
+    }
+    if let Err(e) = validate::write_json(output, path, &reports) {
+        panic!("Failed to write {}: {}", output.display(), e);
+    }
+    println!("Validation report written to {}", output.display());
+    if reports.iter().any(|r| !r.issues.is_empty()) {
+        process::exit(1);
+    }
+}
+
+/// Measure the models without opening a window, writing the JSON results to `output`
+/// or printing them if there's no output file
+fn measure(args: &cli::Args, output: Option<&Path>) {
+    let path = Path::new(&args.model_file);
+    let mut warnings = Vec::new();
+    let models = match support::load_obj(path, &mut warnings) {
+        Ok((models, _)) => models,
+        Err(e) => panic!("Loading of {:?} failed due to {:?}", path, e),
+    };
+    for w in &warnings {
+        eprintln!("Warning: {}", w);
+    }
+    let measurements: Vec<_> = models
+        .iter()
+        .map(|m| {
+            let adjacency = EdgeAdjacency::new(&m.mesh.positions, &m.mesh.indices);
+            let measured = measure::measure(
+                &m.mesh.positions,
+                &m.mesh.indices,
+                &adjacency,
+                &args.load_options,
+            );
+            (m.name.clone(), measured)
+        })
+        .collect();
+    let result = match output {
+        Some(output) => {
+            File::create(output).and_then(|mut f| measure::write_json(&mut f, path, &measurements))
+        }
+        None => measure::write_json(&mut io::stdout(), path, &measurements),
+    };
+    if let Err(e) = result {
+        panic!("Failed to write measurements: {}", e);
+    }
+}
+
+/// Returns the window title summarizing the scene and the measured distance
+fn window_title(path: &str, scene: &Scene, ruler: &Ruler) -> String {
+    let num_vertices: usize = scene.models.iter().map(|m| m.num_vertices).sum();
+    let mut title = format!(
+        "{} - {} models, {} triangles, {} vertices",
+        path,
+        scene.models.len(),
+        scene.num_triangles(),
+        num_vertices
+    );
+    if let Some(d) = ruler.distance() {
+        title.push_str(&format!(" - distance: {:.4}", d));
+    }
+    title
+}
+
+/// Returns the flythrough file given on the command line or the model's sidecar file
+fn flythrough_path(args: &cli::Args) -> PathBuf {
+    match args.flythrough_file {
+        Some(ref f) => f.clone(),
+        None => Flythrough::sidecar_path(Path::new(&args.model_file)),
+    }
+}
+
+// This code is essentially straight from the glium teapot example
+fn view(args: &cli::Args) {
+    let mut load_options = args.load_options;
 
     // building the display, ie. the main object
     let mut events_loop = glutin::EventsLoop::new();
     let window = glutin::WindowBuilder::new();
-    let context = glutin::ContextBuilder::new().with_depth_buffer(24);
+    let context = glutin::ContextBuilder::new()
+        .with_depth_buffer(24)
+        .with_stencil_buffer(8);
     let display = glium::Display::new(window, context, &events_loop).unwrap();
 
     // building the vertex and index buffers
-    let (mut vertex_buffer, mut scale) = support::load_wavefront(&display, Path::new(&model_file));
-
-    // the program
-    let program = program!(&display,
-        140 => {
-            vertex: "
-                #version 140
-
-                uniform mat4 persp_matrix;
-                uniform mat4 view_matrix;
-
-                in vec3 position;
-                in vec3 normal;
-                out vec3 v_position;
-                out vec3 v_normal;
-
-                void main() {
-                    v_position = position;
-                    v_normal = normal;
-                    gl_Position = persp_matrix * view_matrix * vec4(v_position * 0.005, 1.0);
-                }
-            ",
-
-            fragment: "
-                #version 140
-
-                in vec3 v_normal;
-                out vec4 f_color;
-
-                const vec3 LIGHT = vec3(-0.2, 0.8, 0.1);
-
-                void main() {
-                    float lum = max(dot(normalize(v_normal), normalize(LIGHT)), 0.0);
-                    vec3 color = (0.3 + 0.7 * lum) * vec3(1.0, 1.0, 1.0);
-                    f_color = vec4(color, 1.0);
-                }
-            ",
-        },
-
-        110 => {
-            vertex: "
-                #version 110
-
-                uniform mat4 persp_matrix;
-                uniform mat4 view_matrix;
-
-                attribute vec3 position;
-                attribute vec3 normal;
-                varying vec3 v_position;
-                varying vec3 v_normal;
-
-                void main() {
-                    v_position = position;
-                    v_normal = normal;
-                    gl_Position = persp_matrix * view_matrix * vec4(v_position * 0.005, 1.0);
-                }
-            ",
-
-            fragment: "
-                #version 110
-
-                varying vec3 v_normal;
-
-                const vec3 LIGHT = vec3(-0.2, 0.8, 0.1);
-
-                void main() {
-                    float lum = max(dot(normalize(v_normal), normalize(LIGHT)), 0.0);
-                    vec3 color = (0.3 + 0.7 * lum) * vec3(1.0, 1.0, 1.0);
-                    gl_FragColor = vec4(color, 1.0);
-                }
-            ",
-        },
-
-        100 => {
-            vertex: "
-                #version 100
-
-                uniform lowp mat4 persp_matrix;
-                uniform lowp mat4 view_matrix;
-
-                attribute lowp vec3 position;
-                attribute lowp vec3 normal;
-                varying lowp vec3 v_position;
-                varying lowp vec3 v_normal;
-
-                void main() {
-                    v_position = position;
-                    v_normal = normal;
-                    gl_Position = persp_matrix * view_matrix * vec4(v_position * 0.005, 1.0);
-                }
-            ",
-
-            fragment: "
-                #version 100
-
-                varying lowp vec3 v_normal;
-
-                const lowp vec3 LIGHT = vec3(-0.2, 0.8, 0.1);
-
-                void main() {
-                    lowp float lum = max(dot(normalize(v_normal), normalize(LIGHT)), 0.0);
-                    lowp vec3 color = (0.3 + 0.7 * lum) * vec3(1.0, 1.0, 1.0);
-                    gl_FragColor = vec4(color, 1.0);
-                }
-            ",
-        },
-    )
-    .unwrap();
+    let mut scene = load_scene(&display, Path::new(&args.model_file), &load_options);
+    let mut clip_planes = ClipPlanes::new(&scene.bounds);
+    let mut ruler = Ruler::new();
+    display
+        .gl_window()
+        .set_title(&window_title(&args.model_file, &scene, &ruler));
+    let mut renderer = Renderer::new(&display, &scene);
+    println!(
+        "Ground grid spacing: {} model units",
+        renderer.overlays.grid_step
+    );
+    if let Some(ref path) = args.environment {
+        match Environment::load(&display, path) {
+            Ok(e) => renderer.set_environment(Rc::new(e)),
+            Err(e) => println!("Failed to load environment {}: {}", path.display(), e),
+        }
+    }
+    // Like the bookmarks, lights edited after the file failed to load aren't saved over it
+    let save_lights = match lights::load(Path::new(&args.model_file)) {
+        Ok(l) => {
+            renderer.lights = l;
+            true
+        }
+        Err(e) => {
+            println!(
+                "Failed to load lights: {}, light edits will only last for this session",
+                e
+            );
+            false
+        }
+    };
+    let mut selected_light = 0;
 
     let mut camera = support::camera::CameraState::new();
-    let mut mouse_pressed = [false; 3];
+    // Bookmarks stored after the file failed to load are kept for this session only, so
+    // the bookmarks in the file aren't lost by overwriting it
+    let (mut bookmarks, save_bookmarks) = match Bookmarks::load(Path::new(&args.model_file)) {
+        Ok(b) => (b, true),
+        Err(e) => {
+            println!(
+                "Failed to load camera bookmarks: {}, new bookmarks won't be saved",
+                e
+            );
+            (Bookmarks::new(Path::new(&args.model_file)), false)
+        }
+    };
+    let fly_path = flythrough_path(args);
+    // Like the bookmarks, keyframes recorded after the path failed to load aren't saved over it
+    let (mut flythrough, save_flythrough) = match Flythrough::load(&fly_path) {
+        Ok(f) => (f, true),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => (Flythrough::new(), true),
+        Err(e) => {
+            println!(
+                "Failed to load flythrough {}: {}, new keyframes won't be saved",
+                fly_path.display(),
+                e
+            );
+            (Flythrough::new(), false)
+        }
+    };
+    let mut playback: Option<Instant> = None;
     let mut mouse_pos = (0.0, 0.0);
+    let mut selected: Option<usize> = None;
+    let mut explode = Explode::new();
 
     // the main loop
     support::start_loop(|| {
+        if let Some(start) = playback {
+            let elapsed = start.elapsed();
+            let t = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9;
+            if let Some(pose) = flythrough.sample(t) {
+                camera.set_pose(&pose);
+            }
+            if t >= flythrough.duration() {
+                playback = None;
+            }
+        }
+        if explode.update() {
+            scene.set_explode(explode.amount());
+            renderer.overlays.set_models(&display, &scene);
+            let outlined: Vec<_> = selected.into_iter().collect();
+            renderer.overlays.set_selection(&display, &scene, &outlined);
+        }
         camera.update();
-
-        // building the uniforms
-        let uniforms = uniform! {
-            persp_matrix: camera.get_perspective(),
-            view_matrix: camera.get_view(),
-        };
-
-        // draw parameters
-        let params = glium::DrawParameters {
-            depth: glium::Depth {
-                test: glium::DepthTest::IfLess,
-                write: true,
-                ..Default::default()
-            },
-            ..Default::default()
-        };
+        camera.fit_clip_planes(&scene.world_bounds());
 
         // drawing a frame
         let mut target = display.draw();
-        target.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
-        target
-            .draw(
-                &vertex_buffer,
-                &glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
-                &program,
-                &uniforms,
-                &params,
-            )
-            .unwrap();
+        renderer.draw(&mut target, &camera, &scene);
         target.finish().unwrap();
 
         let mut action = support::Action::Continue;
+        let mut reload = false;
+        let mut pick = false;
+        let mut select = false;
+        let mut update_visibility = false;
+        let mut update_ruler = false;
+        let mut update_clipping = false;
+        let mut update_lights = false;
 
         // polling and handling the events received by the window
-        events_loop.poll_events(|event| match event {
-            glutin::Event::WindowEvent { event, .. } => match event {
-                glutin::WindowEvent::Closed => action = support::Action::Stop,
-                ev => camera.process_input(&ev),
-            },
-            _ => (),
+        events_loop.poll_events(|event| {
+            if let glutin::Event::WindowEvent { event, .. } = event {
+                match event {
+                    glutin::WindowEvent::Closed => action = support::Action::Stop,
+                    // A minimized window can report a zero height
+                    glutin::WindowEvent::Resized(width, height) if height > 0 => {
+                        camera.set_aspect_ratio(width as f32 / height as f32)
+                    }
+                    glutin::WindowEvent::KeyboardInput {
+                        input:
+                            glutin::KeyboardInput {
+                                state: glutin::ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                modifiers,
+                                ..
+                            },
+                        ..
+                    } => match key {
+                        glutin::VirtualKeyCode::B => {
+                            renderer.overlays.show_bounds = !renderer.overlays.show_bounds
+                        }
+                        glutin::VirtualKeyCode::G => {
+                            renderer.overlays.show_grid = !renderer.overlays.show_grid
+                        }
+                        glutin::VirtualKeyCode::X => {
+                            renderer.overlays.show_axes = !renderer.overlays.show_axes
+                        }
+                        glutin::VirtualKeyCode::Numpad1 => camera.frame(
+                            &scene.world_bounds(),
+                            if modifiers.ctrl {
+                                ViewPreset::Back
+                            } else {
+                                ViewPreset::Front
+                            },
+                        ),
+                        glutin::VirtualKeyCode::Numpad3 => camera.frame(
+                            &scene.world_bounds(),
+                            if modifiers.ctrl {
+                                ViewPreset::Left
+                            } else {
+                                ViewPreset::Right
+                            },
+                        ),
+                        glutin::VirtualKeyCode::Numpad7 => camera.frame(
+                            &scene.world_bounds(),
+                            if modifiers.ctrl {
+                                ViewPreset::Bottom
+                            } else {
+                                ViewPreset::Top
+                            },
+                        ),
+                        glutin::VirtualKeyCode::Numpad0 => {
+                            camera.frame(&scene.world_bounds(), ViewPreset::Iso)
+                        }
+                        glutin::VirtualKeyCode::Numpad5 => camera.toggle_projection(),
+                        glutin::VirtualKeyCode::LBracket => {
+                            camera.set_fov(camera.fov() - FOV_STEP);
+                            println!("Field of view: {:.0} degrees", camera.fov().to_degrees());
+                        }
+                        glutin::VirtualKeyCode::RBracket => {
+                            camera.set_fov(camera.fov() + FOV_STEP);
+                            println!("Field of view: {:.0} degrees", camera.fov().to_degrees());
+                        }
+                        glutin::VirtualKeyCode::Key1
+                        | glutin::VirtualKeyCode::Key2
+                        | glutin::VirtualKeyCode::Key3
+                        | glutin::VirtualKeyCode::Key4
+                        | glutin::VirtualKeyCode::Key5
+                        | glutin::VirtualKeyCode::Key6
+                        | glutin::VirtualKeyCode::Key7
+                        | glutin::VirtualKeyCode::Key8
+                        | glutin::VirtualKeyCode::Key9 => {
+                            let slot = key as u32 - glutin::VirtualKeyCode::Key1 as u32 + 1;
+                            if modifiers.ctrl {
+                                bookmarks.set(slot, camera.pose());
+                                if save_bookmarks {
+                                    match bookmarks.save() {
+                                        Ok(_) => println!(
+                                            "Saved camera to slot {} in {}",
+                                            slot,
+                                            bookmarks.path().display()
+                                        ),
+                                        Err(e) => {
+                                            println!("Failed to save camera bookmarks: {}", e)
+                                        }
+                                    }
+                                } else {
+                                    println!(
+                                    "Stored camera in slot {} for this session, not overwriting {}",
+                                    slot,
+                                    bookmarks.path().display()
+                                );
+                                }
+                            } else if let Some(b) = bookmarks.get(slot) {
+                                println!("Camera bookmark {}: {}", slot, b.name);
+                                camera.set_pose(&b.pose);
+                            }
+                        }
+                        glutin::VirtualKeyCode::K => {
+                            if modifiers.ctrl {
+                                flythrough.clear();
+                                println!("Cleared flythrough keyframes");
+                            } else {
+                                flythrough.add(camera.pose());
+                                println!(
+                                    "Added flythrough keyframe {}",
+                                    flythrough.keyframes().len()
+                                );
+                            }
+                            if !save_flythrough {
+                                println!(
+                                    "Keeping keyframes for this session, not overwriting {}",
+                                    fly_path.display()
+                                );
+                            } else if let Err(e) = flythrough.save(&fly_path) {
+                                println!("Failed to save flythrough: {}", e);
+                            }
+                        }
+                        glutin::VirtualKeyCode::P => {
+                            playback = match playback {
+                                Some(_) => None,
+                                None if !flythrough.is_empty() => Some(Instant::now()),
+                                None => None,
+                            };
+                        }
+                        glutin::VirtualKeyCode::I => stats::print_report(&scene),
+                        glutin::VirtualKeyCode::H => {
+                            renderer.overlays.show_open_edges = !renderer.overlays.show_open_edges
+                        }
+                        glutin::VirtualKeyCode::V => {
+                            renderer.overlays.show_issues = !renderer.overlays.show_issues
+                        }
+                        glutin::VirtualKeyCode::C => {
+                            if modifiers.ctrl {
+                                clip_planes.select_next();
+                            } else {
+                                let plane = clip_planes.selected();
+                                plane.enabled = !plane.enabled;
+                            }
+                            update_clipping = true;
+                        }
+                        glutin::VirtualKeyCode::PageUp => {
+                            clip_planes.move_selected(1.0);
+                            update_clipping = true;
+                        }
+                        glutin::VirtualKeyCode::PageDown => {
+                            clip_planes.move_selected(-1.0);
+                            update_clipping = true;
+                        }
+                        glutin::VirtualKeyCode::Home => {
+                            let plane = clip_planes.selected();
+                            plane.flipped = !plane.flipped;
+                            update_clipping = true;
+                        }
+                        glutin::VirtualKeyCode::M => {
+                            ruler.clear();
+                            update_ruler = true;
+                        }
+                        glutin::VirtualKeyCode::N => {
+                            ruler.snap = !ruler.snap;
+                            println!("Snap to vertex: {}", ruler.snap);
+                        }
+                        glutin::VirtualKeyCode::Tab if !scene.models.is_empty() => {
+                            let n = scene.models.len();
+                            selected = Some(match selected {
+                                Some(i) if modifiers.shift => (i + n - 1) % n,
+                                Some(i) => (i + 1) % n,
+                                None if modifiers.shift => n - 1,
+                                None => 0,
+                            });
+                            update_visibility = true;
+                        }
+                        glutin::VirtualKeyCode::L => stats::print_model_list(&scene, selected),
+                        glutin::VirtualKeyCode::Delete => {
+                            if let Some(i) = selected {
+                                if modifiers.ctrl {
+                                    let group = scene.group(i);
+                                    scene.hide(&group);
+                                } else {
+                                    scene.hide(&[i]);
+                                }
+                                update_visibility = true;
+                            }
+                        }
+                        glutin::VirtualKeyCode::Insert => {
+                            if let Some(i) = selected {
+                                if modifiers.ctrl {
+                                    let group = scene.group(i);
+                                    scene.isolate(&group);
+                                } else {
+                                    scene.isolate(&[i]);
+                                }
+                                update_visibility = true;
+                            }
+                        }
+                        glutin::VirtualKeyCode::End => {
+                            scene.show_all();
+                            update_visibility = true;
+                        }
+                        glutin::VirtualKeyCode::E => {
+                            if modifiers.shift {
+                                explode.reset();
+                            } else if modifiers.ctrl {
+                                explode.step(-1.0);
+                            } else {
+                                explode.step(1.0);
+                            }
+                            println!("Exploded view: {:.1}", explode.target());
+                        }
+                        glutin::VirtualKeyCode::T => {
+                            if renderer.supports_order_independent() {
+                                renderer.order_independent = !renderer.order_independent;
+                                println!(
+                                    "Transparency: {}",
+                                    if renderer.order_independent {
+                                        "weighted blended order independent"
+                                    } else {
+                                        "sorted blending"
+                                    }
+                                );
+                            } else {
+                                println!("Order independent transparency needs GLSL 1.40");
+                            }
+                        }
+                        glutin::VirtualKeyCode::O => {
+                            let n = renderer.lights.len();
+                            if n > 0 {
+                                selected_light = if modifiers.shift {
+                                    (selected_light + n - 1) % n
+                                } else {
+                                    (selected_light + 1) % n
+                                };
+                                println!(
+                                    "Light {} of {}: {}",
+                                    selected_light + 1,
+                                    n,
+                                    renderer.lights[selected_light]
+                                );
+                            }
+                        }
+                        glutin::VirtualKeyCode::J => {
+                            // The camera in model space, where the lights are placed
+                            let pose = camera.pose();
+                            let eye = [
+                                pose.position.0 / MODEL_SCALE,
+                                pose.position.1 / MODEL_SCALE,
+                                pose.position.2 / MODEL_SCALE,
+                            ];
+                            let dir = [pose.direction.0, pose.direction.1, pose.direction.2];
+                            let at_eye = Light::at_eye(eye, dir, 0.5 * scene.bounds.diagonal());
+                            if modifiers.ctrl {
+                                if renderer.lights.len() < MAX_LIGHTS {
+                                    renderer.lights.push(at_eye);
+                                    selected_light = renderer.lights.len() - 1;
+                                    update_lights = true;
+                                } else {
+                                    println!("At most {} lights are supported", MAX_LIGHTS);
+                                }
+                            } else if selected_light < renderer.lights.len() {
+                                if modifiers.shift {
+                                    renderer.lights.remove(selected_light);
+                                    selected_light = selected_light.saturating_sub(1);
+                                } else {
+                                    let light = &mut renderer.lights[selected_light];
+                                    light.position = at_eye.position;
+                                    light.direction = at_eye.direction;
+                                }
+                                update_lights = true;
+                            }
+                        }
+                        glutin::VirtualKeyCode::Q if selected_light < renderer.lights.len() => {
+                            let light = &mut renderer.lights[selected_light];
+                            light.kind = light.kind.next();
+                            update_lights = true;
+                        }
+                        glutin::VirtualKeyCode::F2 if selected_light < renderer.lights.len() => {
+                            let light = &mut renderer.lights[selected_light];
+                            light.headlight = !light.headlight;
+                            update_lights = true;
+                        }
+                        glutin::VirtualKeyCode::Minus | glutin::VirtualKeyCode::Equals
+                            if selected_light < renderer.lights.len() =>
+                        {
+                            renderer.lights[selected_light].scale(
+                                if key == glutin::VirtualKeyCode::Minus {
+                                    1.0 / LIGHT_STEP
+                                } else {
+                                    LIGHT_STEP
+                                },
+                            );
+                            update_lights = true;
+                        }
+                        glutin::VirtualKeyCode::Y => {
+                            if renderer.has_skybox() {
+                                renderer.show_skybox = !renderer.show_skybox;
+                                println!("Skybox: {}", renderer.show_skybox);
+                            } else {
+                                println!("No environment map loaded, use --environment FILE.hdr");
+                            }
+                        }
+                        glutin::VirtualKeyCode::F3 => {
+                            if renderer.supports_shadows() {
+                                renderer.shadows = !renderer.shadows;
+                                println!("Shadows: {}", renderer.shadows);
+                            } else {
+                                println!("Shadow maps are not supported by this OpenGL context");
+                            }
+                        }
+                        glutin::VirtualKeyCode::R => {
+                            renderer.physically_based = !renderer.physically_based;
+                            println!(
+                                "Shading: {}",
+                                if renderer.physically_based {
+                                    "metallic/roughness GGX"
+                                } else {
+                                    "Blinn-Phong"
+                                }
+                            );
+                        }
+                        glutin::VirtualKeyCode::F1 => {
+                            renderer.normal_mapping = !renderer.normal_mapping;
+                            println!("Normal mapping: {}", renderer.normal_mapping);
+                        }
+                        glutin::VirtualKeyCode::U => {
+                            load_options.orientation = load_options.orientation.next();
+                            reload = true;
+                        }
+                        glutin::VirtualKeyCode::F => {
+                            load_options.flip_winding = !load_options.flip_winding;
+                            reload = true;
+                        }
+                        _ => camera.process_input(&event),
+                    },
+                    glutin::WindowEvent::CursorMoved {
+                        position: (x, y), ..
+                    } => {
+                        mouse_pos = (x as f32, y as f32);
+                    }
+                    glutin::WindowEvent::MouseInput {
+                        state: glutin::ElementState::Pressed,
+                        button: glutin::MouseButton::Left,
+                        ..
+                    } => pick = true,
+                    glutin::WindowEvent::MouseInput {
+                        state: glutin::ElementState::Pressed,
+                        button: glutin::MouseButton::Right,
+                        ..
+                    } => select = true,
+                    ev => camera.process_input(&ev),
+                }
+            }
         });
 
+        if pick || select {
+            let (width, height) = display.get_framebuffer_dimensions();
+            let x = 2.0 * mouse_pos.0 / width as f32 - 1.0;
+            let y = 1.0 - 2.0 * mouse_pos.1 / height as f32;
+            match camera
+                .pick_ray(x, y)
+                .and_then(|(origin, dir)| scene.pick(origin, dir, &clip_planes))
+            {
+                Some((_, model)) if select => {
+                    selected = Some(model);
+                    update_visibility = true;
+                }
+                Some((hit, model)) => {
+                    let point = if ruler.snap {
+                        scene.snap_to_vertex(&hit, model)
+                    } else {
+                        hit.point
+                    };
+                    ruler.add(point);
+                    match ruler.distance() {
+                        Some(d) => println!("Distance: {} model units", d),
+                        None => println!(
+                            "Picked [{:.4}, {:.4}, {:.4}], click a second point to measure",
+                            point[0], point[1], point[2]
+                        ),
+                    }
+                    update_ruler = true;
+                }
+                None => println!("No surface under the cursor"),
+            }
+        }
+
+        if reload {
+            scene = load_scene(&display, Path::new(&args.model_file), &load_options);
+            renderer.set_scene(&display, &scene);
+            ruler.clear();
+            update_ruler = true;
+            clip_planes = ClipPlanes::new(&scene.bounds);
+            update_clipping = true;
+            selected = None;
+            explode.clear();
+        }
+        if update_visibility {
+            renderer.overlays.set_models(&display, &scene);
+            let outlined: Vec<_> = selected.into_iter().collect();
+            renderer.overlays.set_selection(&display, &scene, &outlined);
+            if let Some(i) = selected {
+                println!(
+                    "Selected model {}: {}{}",
+                    i,
+                    scene.models[i].name,
+                    if scene.meshes[i].visible {
+                        ""
+                    } else {
+                        " (hidden)"
+                    }
+                );
+            }
+            println!(
+                "Showing {} of {} models",
+                scene.num_visible(),
+                scene.models.len()
+            );
+        }
+        if update_clipping {
+            renderer.set_clip_planes(&display, &clip_planes);
+            let selected = clip_planes.planes[clip_planes.selected];
+            println!(
+                "Section plane {}: {}, at {:.4}{}",
+                ["X", "Y", "Z"][selected.axis],
+                if selected.enabled { "on" } else { "off" },
+                selected.offset,
+                if selected.flipped { ", flipped" } else { "" }
+            );
+        }
+        if update_lights {
+            if selected_light < renderer.lights.len() {
+                println!(
+                    "Light {} of {}: {}",
+                    selected_light + 1,
+                    renderer.lights.len(),
+                    renderer.lights[selected_light]
+                );
+            } else {
+                println!("No lights");
+            }
+            if !save_lights {
+                println!(
+                    "Keeping lights for this session, not overwriting {}",
+                    lights::sidecar_path(Path::new(&args.model_file)).display()
+                );
+            } else {
+                match lights::save(Path::new(&args.model_file), &renderer.lights) {
+                    Ok(path) => println!("Saved lights to {}", path.display()),
+                    Err(e) => println!("Failed to save lights: {}", e),
+                }
+            }
+        }
+        if update_ruler {
+            renderer.overlays.set_ruler(
+                &display,
+                &ruler.lines(scene.bounds.diagonal()),
+                ruler.label(),
+            );
+            display
+                .gl_window()
+                .set_title(&window_title(&args.model_file, &scene, &ruler));
+        }
+
         action
     });
 
@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use support::camera::CameraPose;
+
+/// A camera pose saved in one of the numbered bookmark slots
+#[derive(Clone, Debug)]
+pub struct Bookmark {
+    pub name: String,
+    pub pose: CameraPose,
+}
+
+/// Camera bookmarks for a model, persisted to a sidecar `.views` file next to the OBJ.
+/// Each line of the file stores one slot as
+///
+/// `slot px py pz dx dy dz ux uy uz fov perspective|orthographic ortho_height name`
+///
+/// where the name is the rest of the line, so it can be edited to describe the view.
+pub struct Bookmarks {
+    path: PathBuf,
+    slots: BTreeMap<u32, Bookmark>,
+}
+
+impl Bookmarks {
+    /// Returns the path of the sidecar file storing the bookmarks for the model
+    pub fn sidecar_path(model_path: &Path) -> PathBuf {
+        model_path.with_extension("views")
+    }
+
+    /// Create an empty set of bookmarks for the model
+    pub fn new(model_path: &Path) -> Bookmarks {
+        Bookmarks {
+            path: Bookmarks::sidecar_path(model_path),
+            slots: BTreeMap::new(),
+        }
+    }
+
+    /// Load the bookmarks saved for the model, if the sidecar file doesn't exist
+    /// there are no bookmarks yet.
+    pub fn load(model_path: &Path) -> io::Result<Bookmarks> {
+        let path = Bookmarks::sidecar_path(model_path);
+        let mut slots = BTreeMap::new();
+        let file = match File::open(&path) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Bookmarks::new(model_path));
+            }
+            Err(e) => return Err(e),
+        };
+        for (line_num, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_bookmark(line) {
+                Some((slot, bookmark)) => {
+                    slots.insert(slot, bookmark);
+                }
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}:{}: invalid bookmark", path.display(), line_num + 1),
+                    ));
+                }
+            }
+        }
+        Ok(Bookmarks { path, slots })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, slot: u32) -> Option<&Bookmark> {
+        self.slots.get(&slot)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&u32, &Bookmark)> {
+        self.slots.iter()
+    }
+
+    /// Store the pose in the slot, keeping the name of any bookmark it replaces
+    pub fn set(&mut self, slot: u32, pose: CameraPose) {
+        let name = match self.slots.get(&slot) {
+            Some(b) => b.name.clone(),
+            None => format!("View {}", slot),
+        };
+        self.slots.insert(slot, Bookmark { name, pose });
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut file = File::create(&self.path)?;
+        writeln!(
+            file,
+            "# slot position direction up fov projection ortho_height name"
+        )?;
+        for (slot, b) in &self.slots {
+            writeln!(file, "{} {} {}", slot, b.pose, b.name)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_bookmark(line: &str) -> Option<(u32, Bookmark)> {
+    let mut words = line.split_whitespace();
+    let slot = u32::from_str(words.next()?).ok()?;
+    let pose = CameraPose::parse(&mut words)?;
+    let name = words.collect::<Vec<_>>().join(" ");
+    Some((slot, Bookmark { name, pose }))
+}
@@ -0,0 +1,102 @@
+use std::f32;
+
+/// An axis-aligned bounding box in model space.
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Bounds {
+    /// Returns an empty box which any point will extend.
+    pub fn empty() -> Bounds {
+        Bounds {
+            min: [f32::INFINITY; 3],
+            max: [f32::NEG_INFINITY; 3],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.min[i] > self.max[i])
+    }
+
+    pub fn extend(&mut self, pos: [f32; 3]) {
+        for (i, p) in pos.iter().enumerate() {
+            self.min[i] = f32::min(self.min[i], *p);
+            self.max[i] = f32::max(self.max[i], *p);
+        }
+    }
+
+    pub fn union(&mut self, other: &Bounds) {
+        if !other.is_empty() {
+            self.extend(other.min);
+            self.extend(other.max);
+        }
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [
+            0.5 * (self.min[0] + self.max[0]),
+            0.5 * (self.min[1] + self.max[1]),
+            0.5 * (self.min[2] + self.max[2]),
+        ]
+    }
+
+    pub fn extent(&self) -> [f32; 3] {
+        [
+            self.max[0] - self.min[0],
+            self.max[1] - self.min[1],
+            self.max[2] - self.min[2],
+        ]
+    }
+
+    pub fn diagonal(&self) -> f32 {
+        let e = self.extent();
+        f32::sqrt(e[0] * e[0] + e[1] * e[1] + e[2] * e[2])
+    }
+
+    /// Returns the box scaled uniformly about the origin by `s`
+    pub fn scaled(&self, s: f32) -> Bounds {
+        if self.is_empty() {
+            return *self;
+        }
+        Bounds {
+            min: [self.min[0] * s, self.min[1] * s, self.min[2] * s],
+            max: [self.max[0] * s, self.max[1] * s, self.max[2] * s],
+        }
+    }
+
+    /// Returns the box moved by `offset`
+    pub fn translated(&self, offset: [f32; 3]) -> Bounds {
+        if self.is_empty() {
+            return *self;
+        }
+        Bounds {
+            min: [
+                self.min[0] + offset[0],
+                self.min[1] + offset[1],
+                self.min[2] + offset[2],
+            ],
+            max: [
+                self.max[0] + offset[0],
+                self.max[1] + offset[1],
+                self.max[2] + offset[2],
+            ],
+        }
+    }
+
+    /// Returns the 8 corners of the box, with bit `i` of the index selecting max on axis `i`.
+    pub fn corners(&self) -> [[f32; 3]; 8] {
+        let mut corners = [[0.0; 3]; 8];
+        for (c, corner) in corners.iter_mut().enumerate() {
+            for (i, x) in corner.iter_mut().enumerate() {
+                *x = if c & (1 << i) != 0 {
+                    self.max[i]
+                } else {
+                    self.min[i]
+                };
+            }
+        }
+        corners
+    }
+}
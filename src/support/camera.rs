@@ -1,9 +1,125 @@
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector4};
 use glium::glutin;
+use std::f32;
+use std::fmt;
+use std::str::FromStr;
+
+use support::bounds::Bounds;
+
+const MIN_FOV: f32 = 10.0 * f32::consts::PI / 180.0;
+const MAX_FOV: f32 = 150.0 * f32::consts::PI / 180.0;
+const MIN_NEAR_FAR_RATIO: f32 = 1e-4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+}
+
+/// Standard views which look at the model along one of the world axes, or diagonally
+/// down from the front right for `Iso`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ViewPreset {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    Iso,
+}
+
+impl ViewPreset {
+    /// Returns the view direction and up vector for the preset
+    fn orientation(&self) -> ((f32, f32, f32), (f32, f32, f32)) {
+        let y_up = (0.0, 1.0, 0.0);
+        match *self {
+            ViewPreset::Front => ((0.0, 0.0, -1.0), y_up),
+            ViewPreset::Back => ((0.0, 0.0, 1.0), y_up),
+            ViewPreset::Left => ((1.0, 0.0, 0.0), y_up),
+            ViewPreset::Right => ((-1.0, 0.0, 0.0), y_up),
+            ViewPreset::Top => ((0.0, -1.0, 0.0), (0.0, 0.0, -1.0)),
+            ViewPreset::Bottom => ((0.0, 1.0, 0.0), (0.0, 0.0, 1.0)),
+            ViewPreset::Iso => ((-1.0, -1.0, -1.0), y_up),
+        }
+    }
+}
+
+/// A saved camera viewpoint
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CameraPose {
+    pub position: (f32, f32, f32),
+    pub direction: (f32, f32, f32),
+    pub up: (f32, f32, f32),
+    pub fov: f32,
+    pub projection: Projection,
+    pub ortho_height: f32,
+}
+
+impl CameraPose {
+    /// Parse a pose written with its `Display` implementation from the whitespace
+    /// separated words: `px py pz dx dy dz ux uy uz fov perspective|orthographic ortho_height`
+    pub fn parse<'a, I>(words: &mut I) -> Option<CameraPose>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut v = [0.0; 10];
+        for x in v.iter_mut() {
+            *x = f32::from_str(words.next()?).ok()?;
+        }
+        let projection = match words.next()? {
+            "perspective" => Projection::Perspective,
+            "orthographic" => Projection::Orthographic,
+            _ => return None,
+        };
+        let ortho_height = f32::from_str(words.next()?).ok()?;
+        Some(CameraPose {
+            position: (v[0], v[1], v[2]),
+            direction: (v[3], v[4], v[5]),
+            up: (v[6], v[7], v[8]),
+            fov: v[9],
+            projection,
+            ortho_height,
+        })
+    }
+}
+
+impl fmt::Display for CameraPose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let projection = match self.projection {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic => "orthographic",
+        };
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {} {} {} {} {}",
+            self.position.0,
+            self.position.1,
+            self.position.2,
+            self.direction.0,
+            self.direction.1,
+            self.direction.2,
+            self.up.0,
+            self.up.1,
+            self.up.2,
+            self.fov,
+            projection,
+            self.ortho_height
+        )
+    }
+}
 
 pub struct CameraState {
     aspect_ratio: f32,
     position: (f32, f32, f32),
     direction: (f32, f32, f32),
+    up: (f32, f32, f32),
+    fov: f32,
+    znear: f32,
+    zfar: f32,
+    projection: Projection,
+    /// Half the height of the view volume in orthographic mode
+    ortho_height: f32,
 
     moving_up: bool,
     moving_left: bool,
@@ -19,6 +135,12 @@ impl CameraState {
             aspect_ratio: 1024.0 / 768.0,
             position: (0.1, 0.1, 1.0),
             direction: (0.0, 0.0, -1.0),
+            up: (0.0, 1.0, 0.0),
+            fov: f32::consts::FRAC_PI_2,
+            znear: 0.1,
+            zfar: 1024.0,
+            projection: Projection::Perspective,
+            ortho_height: 1.0,
             moving_up: false,
             moving_left: false,
             moving_down: false,
@@ -28,6 +150,10 @@ impl CameraState {
         }
     }
 
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+    }
+
     pub fn set_position(&mut self, pos: (f32, f32, f32)) {
         self.position = pos;
     }
@@ -36,12 +162,166 @@ impl CameraState {
         self.direction = dir;
     }
 
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            position: self.position,
+            direction: self.direction,
+            up: self.up,
+            fov: self.fov,
+            projection: self.projection,
+            ortho_height: self.ortho_height,
+        }
+    }
+
+    pub fn set_pose(&mut self, pose: &CameraPose) {
+        self.position = pose.position;
+        self.direction = pose.direction;
+        self.up = pose.up;
+        self.set_fov(pose.fov);
+        self.projection = pose.projection;
+        self.ortho_height = pose.ortho_height;
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn toggle_projection(&mut self) {
+        self.projection = match self.projection {
+            Projection::Perspective => Projection::Orthographic,
+            Projection::Orthographic => Projection::Perspective,
+        };
+    }
+
+    /// The vertical field of view in radians
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov.clamp(MIN_FOV, MAX_FOV);
+    }
+
+    pub fn clip_planes(&self) -> (f32, f32) {
+        (self.znear, self.zfar)
+    }
+
+    /// Fit the near and far planes tightly around the bounds as seen from the camera's
+    /// current position, so small models aren't clipped and large ones don't z-fight.
+    /// The bounding sphere is padded to also cover the ground grid overlay.
+    pub fn fit_clip_planes(&mut self, bounds: &Bounds) {
+        if bounds.is_empty() {
+            return;
+        }
+        let f = {
+            let f = self.direction;
+            let len = f.0 * f.0 + f.1 * f.1 + f.2 * f.2;
+            let len = len.sqrt();
+            (f.0 / len, f.1 / len, f.2 / len)
+        };
+        let c = bounds.center();
+        let radius = f32::max(0.75 * bounds.diagonal(), 1e-6);
+        let depth = (c[0] - self.position.0) * f.0
+            + (c[1] - self.position.1) * f.1
+            + (c[2] - self.position.2) * f.2;
+        match self.projection {
+            Projection::Perspective => {
+                // Everything is behind the camera, keep the planes we had
+                if depth + radius <= 0.0 {
+                    return;
+                }
+                self.zfar = depth + radius;
+                // Bound the far/near ratio to keep some depth precision when inside the model
+                self.znear = f32::max(depth - radius, self.zfar * MIN_NEAR_FAR_RATIO);
+            }
+            Projection::Orthographic => {
+                self.znear = depth - radius;
+                self.zfar = depth + radius;
+            }
+        }
+    }
+
+    /// Place the camera to look at the bounds from the preset direction, with
+    /// the whole box in view for both perspective and orthographic projection.
+    pub fn frame(&mut self, bounds: &Bounds, preset: ViewPreset) {
+        let (dir, up) = preset.orientation();
+        self.frame_along(bounds, dir, up);
+    }
+
+    /// Place the camera to look at the bounds along `dir`, with the whole box in view
+    pub fn frame_along(&mut self, bounds: &Bounds, dir: (f32, f32, f32), up: (f32, f32, f32)) {
+        if bounds.is_empty() {
+            return;
+        }
+        let len = f32::sqrt(dir.0 * dir.0 + dir.1 * dir.1 + dir.2 * dir.2);
+        let dir = (dir.0 / len, dir.1 / len, dir.2 / len);
+        let radius = f32::max(0.5 * bounds.diagonal(), 1e-6);
+        // Fit the bounding sphere in the smaller of the horizontal and vertical fov
+        let half_fov = if self.aspect_ratio < 1.0 {
+            f32::atan(f32::tan(0.5 * self.fov) * self.aspect_ratio)
+        } else {
+            0.5 * self.fov
+        };
+        let distance = radius / f32::sin(half_fov);
+        let center = bounds.center();
+        self.position = (
+            center[0] - dir.0 * distance,
+            center[1] - dir.1 * distance,
+            center[2] - dir.2 * distance,
+        );
+        self.direction = dir;
+        self.up = up;
+        self.ortho_height = if self.aspect_ratio < 1.0 {
+            radius / self.aspect_ratio
+        } else {
+            radius
+        };
+    }
+
+    pub fn get_projection(&self) -> [[f32; 4]; 4] {
+        match self.projection {
+            Projection::Perspective => self.get_perspective(),
+            Projection::Orthographic => self.get_orthographic(),
+        }
+    }
+
+    /// Returns the origin and direction of the ray through a point on the screen, given in
+    /// normalized device coordinates. The origin is on the near plane.
+    pub fn pick_ray(&self, x: f32, y: f32) -> Option<([f32; 3], [f32; 3])> {
+        let proj = Matrix4::from(self.get_projection());
+        let view = Matrix4::from(self.get_view());
+        let inv = (proj * view).invert()?;
+        let unproject = |z: f32| {
+            let p = inv * Vector4::new(x, y, z, 1.0);
+            p.truncate() / p.w
+        };
+        let near = unproject(-1.0);
+        let dir = (unproject(0.0) - near).normalize();
+        Some((near.into(), dir.into()))
+    }
+
+    pub fn get_orthographic(&self) -> [[f32; 4]; 4] {
+        let (znear, zfar) = (self.znear, self.zfar);
+        let h = self.ortho_height;
+
+        // note: remember that this is column-major, so the lines of code are actually columns
+        [
+            [1.0 / (h * self.aspect_ratio), 0.0, 0.0, 0.0],
+            [0.0, 1.0 / h, 0.0, 0.0],
+            [0.0, 0.0, 2.0 / (zfar - znear), 0.0],
+            [0.0, 0.0, -(zfar + znear) / (zfar - znear), 1.0],
+        ]
+    }
+
     pub fn get_perspective(&self) -> [[f32; 4]; 4] {
-        let fov: f32 = 3.141592 / 2.0;
-        let zfar = 1024.0;
-        let znear = 0.1;
+        let zfar = self.zfar;
+        let znear = self.znear;
 
-        let f = 1.0 / (fov / 2.0).tan();
+        let f = 1.0 / (self.fov / 2.0).tan();
 
         // note: remember that this is column-major, so the lines of code are actually columns
         [
@@ -60,7 +340,7 @@ impl CameraState {
             (f.0 / len, f.1 / len, f.2 / len)
         };
 
-        let up = (0.0, 1.0, 0.0);
+        let up = self.up;
 
         let s = (
             f.1 * up.2 - f.2 * up.1,
@@ -103,7 +383,7 @@ impl CameraState {
             (f.0 / len, f.1 / len, f.2 / len)
         };
 
-        let up = (0.0, 1.0, 0.0);
+        let up = self.up;
 
         let s = (
             f.1 * up.2 - f.2 * up.1,
@@ -147,16 +427,28 @@ impl CameraState {
             self.position.2 += s.2 * 0.01;
         }
 
+        // Moving forward or backward doesn't change the size of the model in orthographic
+        // mode, so zoom the view volume instead
         if self.moving_forward {
-            self.position.0 += f.0 * 0.01;
-            self.position.1 += f.1 * 0.01;
-            self.position.2 += f.2 * 0.01;
+            match self.projection {
+                Projection::Perspective => {
+                    self.position.0 += f.0 * 0.01;
+                    self.position.1 += f.1 * 0.01;
+                    self.position.2 += f.2 * 0.01;
+                }
+                Projection::Orthographic => self.ortho_height *= 0.98,
+            }
         }
 
         if self.moving_backward {
-            self.position.0 -= f.0 * 0.01;
-            self.position.1 -= f.1 * 0.01;
-            self.position.2 -= f.2 * 0.01;
+            match self.projection {
+                Projection::Perspective => {
+                    self.position.0 -= f.0 * 0.01;
+                    self.position.1 -= f.1 * 0.01;
+                    self.position.2 -= f.2 * 0.01;
+                }
+                Projection::Orthographic => self.ortho_height /= 0.98,
+            }
         }
     }
 
@@ -0,0 +1,55 @@
+use glium::backend::Facade;
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+use glium::texture::{DepthFormat, MipmapsOption, RawImage2d, UncompressedFloatFormat};
+use glium::{glutin, HeadlessRenderer, Texture2d};
+use image::{self, GenericImage, RgbaImage};
+
+/// Create an OpenGL context without a window for rendering images from the command line
+pub fn headless_context(width: u32, height: u32) -> Result<HeadlessRenderer, String> {
+    let context = glutin::HeadlessRendererBuilder::new(width, height)
+        .build()
+        .map_err(|e| format!("Failed to create headless context: {:?}", e))?;
+    HeadlessRenderer::new(context)
+        .map_err(|e| format!("Failed to create headless renderer: {:?}", e))
+}
+
+/// Render into an offscreen color and depth target of the given size, returning the
+/// rendered image flipped to the usual top-left origin.
+pub fn render_to_image<F, D>(facade: &F, width: u32, height: u32, draw: D) -> RgbaImage
+where
+    F: Facade,
+    D: FnOnce(&mut SimpleFrameBuffer),
+{
+    let color = Texture2d::empty_with_format(
+        facade,
+        UncompressedFloatFormat::U8U8U8U8,
+        MipmapsOption::NoMipmap,
+        width,
+        height,
+    )
+    .unwrap();
+    let depth = DepthRenderBuffer::new(facade, DepthFormat::I24, width, height).unwrap();
+    {
+        let mut target = SimpleFrameBuffer::with_depth_buffer(facade, &color, &depth).unwrap();
+        draw(&mut target);
+    }
+    let raw: RawImage2d<u8> = color.read();
+    let img = RgbaImage::from_raw(raw.width, raw.height, raw.data.into_owned()).unwrap();
+    image::imageops::flip_vertical(&img)
+}
+
+/// Tile the images, which must all be the same size, into a roughly square grid
+pub fn contact_sheet(images: &[RgbaImage]) -> RgbaImage {
+    let (width, height) = match images.first() {
+        Some(img) => img.dimensions(),
+        None => return RgbaImage::new(0, 0),
+    };
+    let columns = f32::ceil(f32::sqrt(images.len() as f32)) as u32;
+    let rows = (images.len() as u32).div_ceil(columns);
+    let mut sheet = RgbaImage::new(columns * width, rows * height);
+    for (i, img) in images.iter().enumerate() {
+        let i = i as u32;
+        sheet.copy_from(img, (i % columns) * width, (i / columns) * height);
+    }
+    sheet
+}
@@ -0,0 +1,131 @@
+use support::bounds::Bounds;
+use support::overlay::LineVertex;
+
+/// The number of section planes, one along each axis
+pub const MAX_CLIP_PLANES: usize = 3;
+/// Fraction of the model's extent moved per step when moving a plane
+const MOVE_STEP: f32 = 0.02;
+const CAP_COLOR: [f32; 3] = [0.8, 0.25, 0.2];
+
+/// A section plane cutting away the part of the model in front of it
+#[derive(Copy, Clone, Debug)]
+pub struct ClipPlane {
+    pub enabled: bool,
+    /// The axis the plane is perpendicular to
+    pub axis: usize,
+    /// Keep the side of the plane with larger coordinates instead of smaller ones
+    pub flipped: bool,
+    /// Position of the plane along its axis in model units
+    pub offset: f32,
+}
+
+impl ClipPlane {
+    /// Returns the plane as `(n, d)` where points with `dot(n, p) + d >= 0` are kept
+    pub fn equation(&self) -> [f32; 4] {
+        if !self.enabled {
+            return [0.0, 0.0, 0.0, 1.0];
+        }
+        let sign = if self.flipped { 1.0 } else { -1.0 };
+        let mut eq = [0.0, 0.0, 0.0, -sign * self.offset];
+        eq[self.axis] = sign;
+        eq
+    }
+
+    pub fn keeps(&self, p: [f32; 3]) -> bool {
+        let eq = self.equation();
+        eq[0] * p[0] + eq[1] * p[1] + eq[2] * p[2] + eq[3] >= 0.0
+    }
+}
+
+/// Axis aligned section planes through the model, selected and moved from the keyboard
+pub struct ClipPlanes {
+    pub planes: [ClipPlane; MAX_CLIP_PLANES],
+    /// The plane being edited
+    pub selected: usize,
+    bounds: Bounds,
+}
+
+impl ClipPlanes {
+    /// Create disabled planes through the center of the bounds, in model units
+    pub fn new(bounds: &Bounds) -> ClipPlanes {
+        let center = bounds.center();
+        let plane = |axis: usize| ClipPlane {
+            enabled: false,
+            axis,
+            flipped: false,
+            offset: center[axis],
+        };
+        ClipPlanes {
+            planes: [plane(0), plane(1), plane(2)],
+            selected: 0,
+            bounds: *bounds,
+        }
+    }
+
+    pub fn any_enabled(&self) -> bool {
+        self.planes.iter().any(|p| p.enabled)
+    }
+
+    /// Returns the equations of the planes, disabled planes keep everything
+    pub fn equations(&self) -> [[f32; 4]; MAX_CLIP_PLANES] {
+        [
+            self.planes[0].equation(),
+            self.planes[1].equation(),
+            self.planes[2].equation(),
+        ]
+    }
+
+    /// Returns true if the point isn't cut away by any plane
+    pub fn keeps(&self, p: [f32; 3]) -> bool {
+        self.planes.iter().all(|plane| plane.keeps(p))
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % MAX_CLIP_PLANES;
+    }
+
+    pub fn selected(&mut self) -> &mut ClipPlane {
+        &mut self.planes[self.selected]
+    }
+
+    /// Move the selected plane by `steps` increments along its axis, keeping it within
+    /// the model's bounds
+    pub fn move_selected(&mut self, steps: f32) {
+        let plane = &mut self.planes[self.selected];
+        let axis = plane.axis;
+        let step = MOVE_STEP * f32::max(self.bounds.extent()[axis], 1e-6);
+        plane.offset = (plane.offset + steps * step)
+            .max(self.bounds.min[axis])
+            .min(self.bounds.max[axis]);
+    }
+
+    /// Returns a quad covering the cross section of the bounds through the plane, to
+    /// be drawn as a triangle list where the stencil marks the inside of the model
+    pub fn cap_quad(&self, plane: usize) -> Vec<LineVertex> {
+        let plane = &self.planes[plane];
+        let (u, v) = ((plane.axis + 1) % 3, (plane.axis + 2) % 3);
+        let corner = |a: f32, b: f32| {
+            let mut p = [0.0; 3];
+            p[plane.axis] = plane.offset;
+            p[u] = a;
+            p[v] = b;
+            LineVertex {
+                position: p,
+                color: CAP_COLOR,
+            }
+        };
+        // Pad the quad so it also covers the section at the edges of the bounds
+        let pad = 0.01 * self.bounds.diagonal();
+        let (min, max) = (self.bounds.min, self.bounds.max);
+        let (u0, u1) = (min[u] - pad, max[u] + pad);
+        let (v0, v1) = (min[v] - pad, max[v] + pad);
+        vec![
+            corner(u0, v0),
+            corner(u1, v0),
+            corner(u1, v1),
+            corner(u0, v0),
+            corner(u1, v1),
+            corner(u0, v1),
+        ]
+    }
+}
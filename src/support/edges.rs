@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+/// Returns the bit pattern of the vector, with -0 and 0 treated as equal
+pub fn position_key(p: [f32; 3]) -> [u32; 3] {
+    [
+        (p[0] + 0.0).to_bits(),
+        (p[1] + 0.0).to_bits(),
+        (p[2] + 0.0).to_bits(),
+    ]
+}
+
+/// An edge shared by one or more triangles, `a` and `b` are the indices of its
+/// vertices in the first triangle found using it
+#[derive(Copy, Clone, Debug)]
+pub struct Edge {
+    pub a: u32,
+    pub b: u32,
+    /// The number of triangles using the edge
+    pub faces: usize,
+}
+
+impl Edge {
+    /// An open edge used by only one triangle
+    pub fn is_boundary(&self) -> bool {
+        self.faces == 1
+    }
+
+    /// An edge shared by more than two triangles
+    pub fn is_non_manifold(&self) -> bool {
+        self.faces > 2
+    }
+}
+
+/// The edges of a triangle mesh and the triangles using them. Vertices are welded by
+/// position first, so seams where tobj split vertices with different normals or texture
+/// coordinates are treated as connected.
+pub struct EdgeAdjacency {
+    /// The index of the first vertex at the same position as each vertex
+    welded: Vec<u32>,
+    /// Every edge, sorted by their welded vertex indices
+    edges: Vec<Edge>,
+}
+
+impl EdgeAdjacency {
+    /// Build the adjacency from flat position and triangle index arrays as stored in
+    /// `tobj::Mesh`, triangles with out of range indices are skipped.
+    pub fn new(positions: &[f32], indices: &[u32]) -> EdgeAdjacency {
+        let num_vertices = positions.len() / 3;
+        let mut by_position = HashMap::new();
+        let welded: Vec<u32> = (0..num_vertices)
+            .map(|i| {
+                let p = [positions[3 * i], positions[3 * i + 1], positions[3 * i + 2]];
+                *by_position.entry(position_key(p)).or_insert(i as u32)
+            })
+            .collect();
+
+        let mut edges: HashMap<(u32, u32), Edge> = HashMap::new();
+        for face in indices.chunks(3) {
+            if face.len() < 3 || face.iter().any(|&i| i as usize >= num_vertices) {
+                continue;
+            }
+            for e in 0..3 {
+                let (a, b) = (face[e], face[(e + 1) % 3]);
+                let (wa, wb) = (welded[a as usize], welded[b as usize]);
+                if wa == wb {
+                    continue;
+                }
+                let key = (u32::min(wa, wb), u32::max(wa, wb));
+                edges.entry(key).or_insert(Edge { a, b, faces: 0 }).faces += 1;
+            }
+        }
+        let mut edges: Vec<_> = edges.into_iter().collect();
+        edges.sort_by_key(|&(k, _)| k);
+        EdgeAdjacency {
+            welded,
+            edges: edges.into_iter().map(|(_, e)| e).collect(),
+        }
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    pub fn boundary_edges<'a>(&'a self) -> impl Iterator<Item = &'a Edge> + 'a {
+        self.edges.iter().filter(|e| e.is_boundary())
+    }
+
+    pub fn non_manifold_edges<'a>(&'a self) -> impl Iterator<Item = &'a Edge> + 'a {
+        self.edges.iter().filter(|e| e.is_non_manifold())
+    }
+
+    /// Returns the number of holes in the mesh, counted as the connected loops
+    /// (or chains) of boundary edges
+    pub fn num_holes(&self) -> usize {
+        let mut parent: HashMap<u32, u32> = HashMap::new();
+        fn find(parent: &mut HashMap<u32, u32>, v: u32) -> u32 {
+            let mut root = v;
+            loop {
+                let p = *parent.entry(root).or_insert(root);
+                if p == root {
+                    break;
+                }
+                root = p;
+            }
+            let mut v = v;
+            while v != root {
+                let next = parent[&v];
+                parent.insert(v, root);
+                v = next;
+            }
+            root
+        }
+        for e in self.boundary_edges() {
+            let a = find(&mut parent, self.welded[e.a as usize]);
+            let b = find(&mut parent, self.welded[e.b as usize]);
+            if a != b {
+                parent.insert(a, b);
+            }
+        }
+        let vertices: Vec<_> = parent.keys().cloned().collect();
+        vertices
+            .into_iter()
+            .filter(|&v| find(&mut parent, v) == v)
+            .count()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A unit cube with its triangles facing outwards. The first two triangles are the -z face
+    /// and the last two the +z face.
+    pub fn cube() -> (Vec<f32>, Vec<u32>) {
+        let mut positions = Vec::new();
+        for i in 0..8 {
+            positions.extend_from_slice(&[(i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2) as f32]);
+        }
+        let quads = [
+            [0, 2, 3, 1],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+            [4, 5, 7, 6],
+        ];
+        let indices = quads
+            .iter()
+            .flat_map(|q| vec![q[0], q[1], q[2], q[0], q[2], q[3]])
+            .collect();
+        (positions, indices)
+    }
+
+    fn holes_and_boundary_edges(positions: &[f32], indices: &[u32]) -> (usize, usize) {
+        let adjacency = EdgeAdjacency::new(positions, indices);
+        (adjacency.num_holes(), adjacency.boundary_edges().count())
+    }
+
+    #[test]
+    fn closed_cube_has_no_open_edges() {
+        let (positions, indices) = cube();
+        let adjacency = EdgeAdjacency::new(&positions, &indices);
+        // The 12 edges of the cube and a diagonal across each face
+        assert_eq!(adjacency.edges().len(), 18);
+        assert!(adjacency.edges().iter().all(|e| e.faces == 2));
+        assert_eq!(adjacency.num_holes(), 0);
+    }
+
+    #[test]
+    fn counts_each_removed_face_as_a_hole() {
+        let (positions, mut indices) = cube();
+        indices.truncate(indices.len() - 6);
+        assert_eq!(holes_and_boundary_edges(&positions, &indices), (1, 4));
+        // Removing the opposite -z face as well leaves a tube with an opening at each end
+        indices.drain(0..6);
+        assert_eq!(holes_and_boundary_edges(&positions, &indices), (2, 8));
+    }
+
+    #[test]
+    fn welds_split_vertices() {
+        let (mut positions, mut indices) = cube();
+        // Give the +z face its own copies of its vertices, as tobj does at a seam
+        let num_vertices = positions.len() as u32 / 3;
+        for &v in &[4, 5, 6, 7] {
+            let p = positions[3 * v..3 * v + 3].to_vec();
+            positions.extend(p);
+        }
+        let n = indices.len();
+        for i in &mut indices[n - 6..] {
+            *i = num_vertices + *i - 4;
+        }
+        assert_eq!(holes_and_boundary_edges(&positions, &indices), (0, 0));
+    }
+
+    #[test]
+    fn finds_non_manifold_edges() {
+        let (mut positions, mut indices) = cube();
+        // A fin sharing the edge between vertices 0 and 1
+        positions.extend_from_slice(&[0.5, -1.0, -1.0]);
+        indices.extend_from_slice(&[0, 1, 8]);
+        let adjacency = EdgeAdjacency::new(&positions, &indices);
+        let non_manifold: Vec<_> = adjacency.non_manifold_edges().collect();
+        assert_eq!(non_manifold.len(), 1);
+        assert_eq!(non_manifold[0].faces, 3);
+    }
+}
@@ -0,0 +1,437 @@
+use std::borrow::Cow;
+use std::f32;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use glium::backend::Facade;
+use glium::texture::{ClientFormat, MipmapsOption, RawImage2d, UncompressedFloatFormat};
+use glium::uniforms::{MinifySamplerFilter, Sampler, SamplerBehavior, SamplerWrapFunction};
+use glium::{self, Program, Rect, Surface, Texture2d, VertexBuffer};
+use image::hdr::HDRDecoder;
+
+use support::overlay::LineVertex;
+
+/// Size of the irradiance map, which varies slowly over the sphere
+const IRRADIANCE_SIZE: (usize, usize) = (32, 16);
+/// Size of the sharpest level of the prefiltered specular map
+const SPECULAR_SIZE: (usize, usize) = (128, 64);
+/// Number of specular mip levels, going from a mirror to a roughness of one
+const SPECULAR_LEVELS: u32 = 6;
+/// Size the environment is reduced to before convolving the rough levels and irradiance
+const CONVOLVE_SIZE: (usize, usize) = (64, 32);
+/// Widest background image kept for the skybox
+const MAX_SKY_WIDTH: usize = 2048;
+
+/// An RGB image with the texels in rows from top to bottom
+struct Image {
+    width: usize,
+    height: usize,
+    texels: Vec<[f32; 3]>,
+}
+
+impl Image {
+    /// Box filter the image down to the given size
+    fn resized(&self, width: usize, height: usize) -> Image {
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let (y0, y1) = span(y, height, self.height);
+            for x in 0..width {
+                let (x0, x1) = span(x, width, self.width);
+                let mut sum = [0.0; 3];
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        let t = self.texels[sy * self.width + sx];
+                        sum = [sum[0] + t[0], sum[1] + t[1], sum[2] + t[2]];
+                    }
+                }
+                let n = ((y1 - y0) * (x1 - x0)) as f32;
+                texels.push([sum[0] / n, sum[1] / n, sum[2] / n]);
+            }
+        }
+        Image {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    /// Convolve the image over the sphere with a lobe around each texel's direction,
+    /// `lobe` gives the weight of a direction from the cosine of its angle to the center
+    fn convolved<L: Fn(f32) -> f32>(&self, width: usize, height: usize, lobe: L) -> Image {
+        let directions: Vec<_> = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let d = direction(x, y, self.width, self.height);
+                // Texels near the poles cover less of the sphere
+                let solid_angle = (1.0 - d[1] * d[1]).sqrt();
+                (d, solid_angle)
+            })
+            .collect();
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let n = direction(x, y, width, height);
+                let mut sum = [0.0; 3];
+                let mut total = 0.0;
+                for (&(d, solid_angle), t) in directions.iter().zip(&self.texels) {
+                    let w = lobe(n[0] * d[0] + n[1] * d[1] + n[2] * d[2]) * solid_angle;
+                    if w > 0.0 {
+                        sum = [sum[0] + w * t[0], sum[1] + w * t[1], sum[2] + w * t[2]];
+                        total += w;
+                    }
+                }
+                let total = if total > 0.0 { total } else { 1.0 };
+                texels.push([sum[0] / total, sum[1] / total, sum[2] / total]);
+            }
+        }
+        Image {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    fn raw(&self) -> RawImage2d<'_, f32> {
+        RawImage2d {
+            data: Cow::Owned(self.texels.iter().flat_map(|t| t.iter().cloned()).collect()),
+            width: self.width as u32,
+            height: self.height as u32,
+            format: ClientFormat::F32F32F32,
+        }
+    }
+}
+
+/// Returns the range of source texels covered by a destination texel when resizing
+fn span(i: usize, dst: usize, src: usize) -> (usize, usize) {
+    let start = i * src / dst;
+    let end = ((i + 1) * src / dst).max(start + 1);
+    (start, end.min(src))
+}
+
+/// Returns the direction through the center of a texel of an equirectangular image, the
+/// inverse of `equirect` in the shaders. The top row looks up along +Y.
+fn direction(x: usize, y: usize, width: usize, height: usize) -> [f32; 3] {
+    let phi = ((x as f32 + 0.5) / width as f32 - 0.5) * 2.0 * f32::consts::PI;
+    let theta = (y as f32 + 0.5) / height as f32 * f32::consts::PI;
+    [
+        theta.sin() * phi.cos(),
+        theta.cos(),
+        theta.sin() * phi.sin(),
+    ]
+}
+
+/// The GGX lobe of a roughness around the mirror direction, taking the view direction
+/// to be along the normal
+fn ggx_lobe(roughness: f32) -> impl Fn(f32) -> f32 {
+    let a2 = roughness.powi(4);
+    move |cos| {
+        if cos <= 0.0 {
+            0.0
+        } else {
+            let d = cos * cos * (a2 - 1.0) + 1.0;
+            a2 / (d * d) * cos
+        }
+    }
+}
+
+/// Image based lighting from an equirectangular environment. The diffuse lighting comes
+/// from the cosine weighted irradiance and the specular from a map whose mip levels are
+/// prefiltered for increasing roughness.
+pub struct Environment {
+    pub irradiance: Texture2d,
+    pub specular: Texture2d,
+    /// The full resolution environment drawn as the background, if it's an image
+    sky: Option<Texture2d>,
+    /// Highest mip level of the specular map, used for a roughness of one
+    pub max_level: f32,
+}
+
+impl Environment {
+    /// Surroundings of a constant brightness, giving the same ambient light from every
+    /// direction
+    pub fn constant<F: Facade>(facade: &F, radiance: f32) -> Environment {
+        let value = (255.0 * radiance).round() as u8;
+        let texture = || {
+            Texture2d::new(
+                facade,
+                RawImage2d {
+                    data: Cow::Owned(vec![value; 3]),
+                    width: 1,
+                    height: 1,
+                    format: ClientFormat::U8U8U8,
+                },
+            )
+            .unwrap()
+        };
+        Environment {
+            irradiance: texture(),
+            specular: texture(),
+            sky: None,
+            max_level: 0.0,
+        }
+    }
+
+    /// Load an equirectangular Radiance HDR image and prefilter it for lighting
+    pub fn load<F: Facade>(facade: &F, path: &Path) -> Result<Environment, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let decoder = HDRDecoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+        let meta = decoder.metadata();
+        let pixels = decoder.read_image_hdr().map_err(|e| e.to_string())?;
+        let image = Image {
+            width: meta.width as usize,
+            height: meta.height as usize,
+            texels: pixels.iter().map(|p| p.data).collect(),
+        };
+        if image.width == 0 || image.height == 0 {
+            return Err("the image is empty".to_owned());
+        }
+
+        let small = image.resized(CONVOLVE_SIZE.0, CONVOLVE_SIZE.1);
+        let irradiance = small.convolved(IRRADIANCE_SIZE.0, IRRADIANCE_SIZE.1, |cos| cos.max(0.0));
+        let irradiance = Texture2d::with_format(
+            facade,
+            irradiance.raw(),
+            UncompressedFloatFormat::F16F16F16,
+            MipmapsOption::NoMipmap,
+        )
+        .map_err(|e| format!("{:?}", e))?;
+
+        let specular = Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F16F16F16,
+            MipmapsOption::EmptyMipmapsMax(SPECULAR_LEVELS - 1),
+            SPECULAR_SIZE.0 as u32,
+            SPECULAR_SIZE.1 as u32,
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        for level in 0..SPECULAR_LEVELS {
+            let (width, height) = (SPECULAR_SIZE.0 >> level, SPECULAR_SIZE.1 >> level);
+            let filtered = if level == 0 {
+                image.resized(width, height)
+            } else {
+                let roughness = level as f32 / (SPECULAR_LEVELS - 1) as f32;
+                small.convolved(width, height, ggx_lobe(roughness))
+            };
+            specular.mipmap(level).unwrap().write(
+                Rect {
+                    left: 0,
+                    bottom: 0,
+                    width: width as u32,
+                    height: height as u32,
+                },
+                filtered.raw(),
+            );
+        }
+
+        let sky = if image.width > MAX_SKY_WIDTH {
+            image.resized(MAX_SKY_WIDTH, MAX_SKY_WIDTH * image.height / image.width)
+        } else {
+            image
+        };
+        let sky = Texture2d::with_format(
+            facade,
+            sky.raw(),
+            UncompressedFloatFormat::F16F16F16,
+            MipmapsOption::NoMipmap,
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        Ok(Environment {
+            irradiance,
+            specular,
+            sky: Some(sky),
+            max_level: (SPECULAR_LEVELS - 1) as f32,
+        })
+    }
+
+    pub fn has_sky(&self) -> bool {
+        self.sky.is_some()
+    }
+}
+
+/// Returns a sampler wrapping around the environment horizontally and clamping at the poles
+pub fn sampler(texture: &Texture2d, mipmaps: bool) -> Sampler<'_, Texture2d> {
+    Sampler(
+        texture,
+        SamplerBehavior {
+            wrap_function: (
+                SamplerWrapFunction::Repeat,
+                SamplerWrapFunction::Clamp,
+                SamplerWrapFunction::Clamp,
+            ),
+            minify_filter: if mipmaps {
+                MinifySamplerFilter::LinearMipmapLinear
+            } else {
+                MinifySamplerFilter::Linear
+            },
+            ..Default::default()
+        },
+    )
+}
+
+/// Draws an environment as the background behind the model
+pub struct Skybox {
+    program: Program,
+    quad: VertexBuffer<LineVertex>,
+}
+
+impl Skybox {
+    pub fn new<F: Facade>(facade: &F) -> Skybox {
+        let corner = |x, y| LineVertex {
+            position: [x, y, 0.0],
+            color: [0.0; 3],
+        };
+        let quad = [
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(-1.0, 1.0),
+            corner(1.0, 1.0),
+        ];
+        Skybox {
+            program: sky_program(facade),
+            quad: VertexBuffer::new(facade, &quad).unwrap(),
+        }
+    }
+
+    /// Draw the environment's image, if it has one, over the whole target. `inv_view_proj`
+    /// is the inverse of the projection and view matrices, so the direction through each
+    /// pixel can be found the same way as picking rays.
+    pub fn draw<S: Surface>(
+        &self,
+        target: &mut S,
+        environment: &Environment,
+        inv_view_proj: [[f32; 4]; 4],
+    ) {
+        let sky = match environment.sky {
+            Some(ref sky) => sky,
+            None => return,
+        };
+        let uniforms = uniform! {
+            inv_view_proj: inv_view_proj,
+            sky: sampler(sky, false),
+        };
+        target
+            .draw(
+                &self.quad,
+                glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
+                &self.program,
+                &uniforms,
+                &Default::default(),
+            )
+            .unwrap();
+    }
+}
+
+fn sky_program<F: Facade>(facade: &F) -> Program {
+    program!(facade,
+        140 => {
+            vertex: "
+                #version 140
+
+                in vec3 position;
+                out vec2 v_ndc;
+
+                void main() {
+                    v_ndc = position.xy;
+                    gl_Position = vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 140
+
+                uniform mat4 inv_view_proj;
+                uniform sampler2D sky;
+
+                in vec2 v_ndc;
+                out vec4 f_color;
+
+                const float PI = 3.14159265;
+
+                vec3 unproject(float z) {
+                    vec4 p = inv_view_proj * vec4(v_ndc, z, 1.0);
+                    return p.xyz / p.w;
+                }
+
+                void main() {
+                    vec3 d = normalize(unproject(0.0) - unproject(-1.0));
+                    vec2 uv = vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+                    f_color = vec4(texture(sky, uv).rgb, 1.0);
+                }
+            ",
+        },
+
+        110 => {
+            vertex: "
+                #version 110
+
+                attribute vec3 position;
+                varying vec2 v_ndc;
+
+                void main() {
+                    v_ndc = position.xy;
+                    gl_Position = vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 110
+
+                uniform mat4 inv_view_proj;
+                uniform sampler2D sky;
+
+                varying vec2 v_ndc;
+
+                const float PI = 3.14159265;
+
+                vec3 unproject(float z) {
+                    vec4 p = inv_view_proj * vec4(v_ndc, z, 1.0);
+                    return p.xyz / p.w;
+                }
+
+                void main() {
+                    vec3 d = normalize(unproject(0.0) - unproject(-1.0));
+                    vec2 uv = vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+                    gl_FragColor = vec4(texture2D(sky, uv).rgb, 1.0);
+                }
+            ",
+        },
+
+        100 => {
+            vertex: "
+                #version 100
+
+                attribute lowp vec3 position;
+                varying highp vec2 v_ndc;
+
+                void main() {
+                    v_ndc = position.xy;
+                    gl_Position = vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 100
+
+                uniform highp mat4 inv_view_proj;
+                uniform lowp sampler2D sky;
+
+                varying highp vec2 v_ndc;
+
+                const highp float PI = 3.14159265;
+
+                highp vec3 unproject(highp float z) {
+                    highp vec4 p = inv_view_proj * vec4(v_ndc, z, 1.0);
+                    return p.xyz / p.w;
+                }
+
+                void main() {
+                    highp vec3 d = normalize(unproject(0.0) - unproject(-1.0));
+                    highp vec2 uv = vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+                    gl_FragColor = vec4(texture2D(sky, uv).rgb, 1.0);
+                }
+            ",
+        },
+    )
+    .unwrap()
+}
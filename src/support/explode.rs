@@ -0,0 +1,76 @@
+use std::time::Instant;
+
+use support::stats::duration_ms;
+
+/// Largest distance the models are pushed out, as a multiple of their distance from
+/// the scene's center
+pub const MAX_EXPLODE: f32 = 2.0;
+/// Change in the explode amount for each press of the explode keys
+pub const EXPLODE_STEP: f32 = 0.1;
+/// Time constant of the easing towards the target amount, in seconds
+const EASE_TIME: f32 = 0.12;
+
+/// Animates the exploded view, easing the amount the models are pushed apart towards
+/// the value set from the keyboard
+pub struct Explode {
+    target: f32,
+    amount: f32,
+    last_update: Instant,
+}
+
+impl Explode {
+    pub fn new() -> Explode {
+        Explode {
+            target: 0.0,
+            amount: 0.0,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// The amount the view eases towards
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// The current amount the models are pushed apart
+    pub fn amount(&self) -> f32 {
+        self.amount
+    }
+
+    /// Move the target by `steps` increments, keeping it in `[0, MAX_EXPLODE]`
+    pub fn step(&mut self, steps: f32) {
+        self.target = (self.target + steps * EXPLODE_STEP).clamp(0.0, MAX_EXPLODE);
+    }
+
+    /// Animate back to the assembled model
+    pub fn reset(&mut self) {
+        self.target = 0.0;
+    }
+
+    /// Put the models back immediately, e.g. when a new scene is loaded
+    pub fn clear(&mut self) {
+        self.target = 0.0;
+        self.amount = 0.0;
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.amount != self.target
+    }
+
+    /// Advance the animation to the current time, called once per frame. Returns true if
+    /// the amount changed.
+    pub fn update(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = (duration_ms(now - self.last_update) / 1000.0) as f32;
+        self.last_update = now;
+        if !self.is_animating() {
+            return false;
+        }
+        self.amount += (self.target - self.amount) * (1.0 - (-elapsed / EASE_TIME).exp());
+        // Snap once close enough so the reset view is exactly the original one
+        if (self.target - self.amount).abs() < 1e-3 * EXPLODE_STEP {
+            self.amount = self.target;
+        }
+        true
+    }
+}
@@ -0,0 +1,266 @@
+use cgmath::{InnerSpace, Matrix3, Quaternion, Vector3};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use support::camera::CameraPose;
+
+/// Seconds between keyframes recorded in the viewer
+pub const KEYFRAME_INTERVAL: f32 = 2.0;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Keyframe {
+    /// Time of the keyframe in seconds from the start of the path
+    pub time: f32,
+    pub pose: CameraPose,
+}
+
+/// A camera path through the scene, positions are interpolated along a Catmull-Rom
+/// spline through the keyframes and orientations are slerped between them.
+/// The path is stored as text with one `time pose` keyframe per line.
+pub struct Flythrough {
+    keyframes: Vec<Keyframe>,
+}
+
+impl Flythrough {
+    pub fn new() -> Flythrough {
+        Flythrough {
+            keyframes: Vec::new(),
+        }
+    }
+
+    /// Returns the path of the sidecar file storing the flythrough recorded for the model
+    pub fn sidecar_path(model_path: &Path) -> PathBuf {
+        model_path.with_extension("fly")
+    }
+
+    pub fn load(path: &Path) -> io::Result<Flythrough> {
+        let mut keyframes = Vec::new();
+        let file = File::open(path)?;
+        for (line_num, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let keyframe = words
+                .next()
+                .and_then(|t| f32::from_str(t).ok())
+                .filter(|t| t.is_finite())
+                .and_then(|time| CameraPose::parse(&mut words).map(|pose| Keyframe { time, pose }));
+            match keyframe {
+                Some(k) => keyframes.push(k),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}:{}: invalid keyframe", path.display(), line_num + 1),
+                    ));
+                }
+            }
+        }
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        Ok(Flythrough { keyframes })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        writeln!(
+            file,
+            "# time position direction up fov projection ortho_height"
+        )?;
+        for k in &self.keyframes {
+            writeln!(file, "{} {}", k.time, k.pose)?;
+        }
+        Ok(())
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Append a keyframe `KEYFRAME_INTERVAL` seconds after the last one
+    pub fn add(&mut self, pose: CameraPose) {
+        let time = match self.keyframes.last() {
+            Some(k) => k.time + KEYFRAME_INTERVAL,
+            None => 0.0,
+        };
+        self.keyframes.push(Keyframe { time, pose });
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+    }
+
+    /// The length of the path in seconds
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    /// Returns the camera pose `time` seconds from the start of the path
+    pub fn sample(&self, time: f32) -> Option<CameraPose> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        let time = first.time + time;
+        if time <= first.time {
+            return Some(first.pose);
+        }
+        if time >= last.time {
+            return Some(last.pose);
+        }
+        let i = self
+            .keyframes
+            .iter()
+            .rposition(|k| k.time <= time)
+            .unwrap_or(0);
+        let k1 = &self.keyframes[i];
+        let k2 = &self.keyframes[i + 1];
+        let k0 = &self.keyframes[i.saturating_sub(1)];
+        let k3 = &self.keyframes[usize::min(i + 2, self.keyframes.len() - 1)];
+        let t = if k2.time > k1.time {
+            (time - k1.time) / (k2.time - k1.time)
+        } else {
+            0.0
+        };
+
+        let position = catmull_rom(
+            to_vec(k0.pose.position),
+            to_vec(k1.pose.position),
+            to_vec(k2.pose.position),
+            to_vec(k3.pose.position),
+            t,
+        );
+        let q1 = pose_rotation(&k1.pose);
+        let mut q2 = pose_rotation(&k2.pose);
+        // Take the shortest path between the orientations
+        if q1.dot(q2) < 0.0 {
+            q2 = -q2;
+        }
+        let rotation = Matrix3::from(q1.slerp(q2, t));
+        Some(CameraPose {
+            position: (position.x, position.y, position.z),
+            direction: (-rotation.z.x, -rotation.z.y, -rotation.z.z),
+            up: (rotation.y.x, rotation.y.y, rotation.y.z),
+            fov: lerp(k1.pose.fov, k2.pose.fov, t),
+            projection: k1.pose.projection,
+            ortho_height: lerp(k1.pose.ortho_height, k2.pose.ortho_height, t),
+        })
+    }
+}
+
+fn to_vec(v: (f32, f32, f32)) -> Vector3<f32> {
+    Vector3::new(v.0, v.1, v.2)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn catmull_rom(
+    p0: Vector3<f32>,
+    p1: Vector3<f32>,
+    p2: Vector3<f32>,
+    p3: Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+/// Returns the rotation taking the x, y and z axes to the camera's side, up and
+/// backward directions
+fn pose_rotation(pose: &CameraPose) -> Quaternion<f32> {
+    let f = to_vec(pose.direction).normalize();
+    let s = f.cross(to_vec(pose.up)).normalize();
+    let u = s.cross(f);
+    Quaternion::from(Matrix3::from_cols(s, u, -f)).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use support::camera::Projection;
+
+    fn pose(x: f32, fov: f32) -> CameraPose {
+        CameraPose {
+            position: (x, 0.0, 0.0),
+            direction: (0.0, 0.0, -1.0),
+            up: (0.0, 1.0, 0.0),
+            fov,
+            projection: Projection::Perspective,
+            ortho_height: 1.0,
+        }
+    }
+
+    /// Keyframes every `KEYFRAME_INTERVAL` seconds moving one unit along x each time
+    fn path(num_keyframes: usize) -> Flythrough {
+        let mut flythrough = Flythrough::new();
+        for i in 0..num_keyframes {
+            flythrough.add(pose(i as f32, 1.0 + i as f32 * 0.1));
+        }
+        flythrough
+    }
+
+    fn assert_close(a: (f32, f32, f32), b: (f32, f32, f32)) {
+        let d = (a.0 - b.0).abs() + (a.1 - b.1).abs() + (a.2 - b.2).abs();
+        assert!(d < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn empty_path_has_no_pose() {
+        assert!(Flythrough::new().sample(0.0).is_none());
+    }
+
+    #[test]
+    fn samples_at_keyframes() {
+        let flythrough = path(4);
+        assert_eq!(flythrough.duration(), 3.0 * KEYFRAME_INTERVAL);
+        for i in 0..4 {
+            let sampled = flythrough.sample(i as f32 * KEYFRAME_INTERVAL).unwrap();
+            let expected = pose(i as f32, 1.0 + i as f32 * 0.1);
+            assert_close(sampled.position, expected.position);
+            assert_close(sampled.direction, expected.direction);
+            assert_close(sampled.up, expected.up);
+            assert!((sampled.fov - expected.fov).abs() < 1e-5);
+        }
+        assert_eq!(flythrough.sample(-1.0), Some(pose(0.0, 1.0)));
+        assert_eq!(flythrough.sample(100.0), Some(pose(3.0, 1.3)));
+    }
+
+    #[test]
+    fn samples_between_keyframes() {
+        let flythrough = path(4);
+        // Evenly spaced keyframes on a line are interpolated linearly by the spline
+        let sampled = flythrough.sample(1.5 * KEYFRAME_INTERVAL).unwrap();
+        assert_close(sampled.position, (1.5, 0.0, 0.0));
+        assert_close(sampled.direction, (0.0, 0.0, -1.0));
+        assert_close(sampled.up, (0.0, 1.0, 0.0));
+        assert!((sampled.fov - 1.15).abs() < 1e-5);
+    }
+
+    #[test]
+    fn slerps_the_orientation() {
+        let mut flythrough = Flythrough::new();
+        flythrough.add(pose(0.0, 1.0));
+        let mut turned = pose(0.0, 1.0);
+        turned.direction = (1.0, 0.0, 0.0);
+        flythrough.add(turned);
+        let sampled = flythrough.sample(0.5 * KEYFRAME_INTERVAL).unwrap();
+        let h = f32::sqrt(0.5);
+        assert_close(sampled.direction, (h, 0.0, -h));
+        assert_close(sampled.up, (0.0, 1.0, 0.0));
+    }
+}
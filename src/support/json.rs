@@ -0,0 +1,25 @@
+/// Returns the string quoted and escaped as a JSON string literal
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Returns the number as a JSON number, or null if it isn't finite
+pub fn number(x: f32) -> String {
+    if x.is_finite() {
+        format!("{}", x)
+    } else {
+        "null".to_owned()
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use cgmath::{InnerSpace, Vector3};
+
+/// Most lights the shaders take at once
+pub const MAX_LIGHTS: usize = 4;
+
+/// Brightness of the default key light
+const KEY_INTENSITY: f32 = 0.7;
+/// Half angle of the cone of new spot lights, in degrees
+const SPOT_ANGLE: f32 = 30.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    /// Infinitely far away, lighting everything from the same direction
+    Directional,
+    /// Shines in every direction from its position
+    Point,
+    /// Shines from its position in a cone around its direction
+    Spot,
+}
+
+impl LightKind {
+    pub fn next(&self) -> LightKind {
+        match *self {
+            LightKind::Directional => LightKind::Point,
+            LightKind::Point => LightKind::Spot,
+            LightKind::Spot => LightKind::Directional,
+        }
+    }
+}
+
+impl fmt::Display for LightKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LightKind::Directional => write!(f, "directional"),
+            LightKind::Point => write!(f, "point"),
+            LightKind::Spot => write!(f, "spot"),
+        }
+    }
+}
+
+impl FromStr for LightKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<LightKind, ()> {
+        match s {
+            "directional" => Ok(LightKind::Directional),
+            "point" => Ok(LightKind::Point),
+            "spot" => Ok(LightKind::Spot),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A light in model space, so it stays in place relative to the model however it's scaled
+#[derive(Copy, Clone, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Position of point and spot lights
+    pub position: [f32; 3],
+    /// Direction the light shines in, for directional and spot lights
+    pub direction: [f32; 3],
+    /// Color scaled by the intensity
+    pub color: [f32; 3],
+    /// Distance at which point and spot lights fall off to half their intensity
+    pub range: f32,
+    /// Half angle of a spot light's cone, in degrees
+    pub angle: f32,
+    /// Follow the camera, shining from the eye along the view direction
+    pub headlight: bool,
+}
+
+impl Light {
+    /// The light shading the model from above when no lights are saved
+    pub fn key() -> Light {
+        let to_light = Vector3::new(-0.2, 0.8, 0.1).normalize();
+        Light {
+            kind: LightKind::Directional,
+            position: [0.0; 3],
+            direction: (-to_light).into(),
+            color: [KEY_INTENSITY; 3],
+            range: 1.0,
+            angle: SPOT_ANGLE,
+            headlight: false,
+        }
+    }
+
+    /// A directional light shining along the view from the eye, with point and spot lights
+    /// placed at the eye falling off over `range`
+    pub fn at_eye(eye: [f32; 3], view_dir: [f32; 3], range: f32) -> Light {
+        Light {
+            position: eye,
+            direction: Vector3::from(view_dir).normalize().into(),
+            range,
+            ..Light::key()
+        }
+    }
+
+    /// Scale the light's brightness
+    pub fn scale(&mut self, factor: f32) {
+        for c in &mut self.color {
+            *c *= factor;
+        }
+    }
+
+    /// Returns the light packed into the columns of a matrix for the shaders, with a
+    /// headlight placed at the eye looking along the view direction. The columns are
+    ///
+    /// - the position, with w = 1, or for directional lights the direction to the light,
+    ///   with w = 0
+    /// - the color, with the range in w
+    /// - the spot direction, with the cosine of the cone's half angle in w, which is
+    ///   below -1 for lights without a cone
+    pub fn uniform(&self, eye: [f32; 3], view_dir: [f32; 3]) -> [[f32; 4]; 4] {
+        let (position, direction) = if self.headlight {
+            (eye, view_dir)
+        } else {
+            (self.position, self.direction)
+        };
+        let direction = Vector3::from(direction).normalize();
+        let first = match self.kind {
+            LightKind::Directional => [-direction.x, -direction.y, -direction.z, 0.0],
+            _ => [position[0], position[1], position[2], 1.0],
+        };
+        let cone = match self.kind {
+            LightKind::Spot => self.angle.to_radians().cos(),
+            _ => -2.0,
+        };
+        [
+            first,
+            [self.color[0], self.color[1], self.color[2], self.range],
+            [direction.x, direction.y, direction.z, cone],
+            [0.0; 4],
+        ]
+    }
+}
+
+impl fmt::Display for Light {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {} {} {} {} {}",
+            self.kind,
+            self.position[0],
+            self.position[1],
+            self.position[2],
+            self.direction[0],
+            self.direction[1],
+            self.direction[2],
+            self.color[0],
+            self.color[1],
+            self.color[2],
+            self.range,
+            self.angle
+        )?;
+        if self.headlight {
+            write!(f, " headlight")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the matrices for the shaders' light uniforms, lights past the end of the list
+/// are all zero, which the shaders skip
+pub fn uniforms(
+    lights: &[Light],
+    eye: [f32; 3],
+    view_dir: [f32; 3],
+) -> [[[f32; 4]; 4]; MAX_LIGHTS] {
+    let mut uniforms = [[[0.0; 4]; 4]; MAX_LIGHTS];
+    for (u, light) in uniforms.iter_mut().zip(lights) {
+        *u = light.uniform(eye, view_dir);
+    }
+    uniforms
+}
+
+/// Returns the path of the sidecar file storing the lights for the model
+pub fn sidecar_path(model_path: &Path) -> PathBuf {
+    model_path.with_extension("lights")
+}
+
+/// Load the lights saved for the model from its sidecar `.lights` file, with one light
+/// per line as
+///
+/// `directional|point|spot px py pz dx dy dz r g b range angle [headlight]`
+///
+/// If the file doesn't exist the model is lit by the key light.
+pub fn load(model_path: &Path) -> io::Result<Vec<Light>> {
+    let path = sidecar_path(model_path);
+    let file = match File::open(&path) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![Light::key()]),
+        Err(e) => return Err(e),
+    };
+    let mut lights = Vec::new();
+    for (line_num, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_light(line) {
+            Some(light) if lights.len() < MAX_LIGHTS => lights.push(light),
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: more than {} lights", path.display(), MAX_LIGHTS),
+                ));
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: invalid light", path.display(), line_num + 1),
+                ));
+            }
+        }
+    }
+    Ok(lights)
+}
+
+/// Save the lights to the model's sidecar file, returning its path
+pub fn save(model_path: &Path, lights: &[Light]) -> io::Result<PathBuf> {
+    let path = sidecar_path(model_path);
+    let mut file = File::create(&path)?;
+    writeln!(
+        file,
+        "# kind position direction color range angle [headlight]"
+    )?;
+    for light in lights {
+        writeln!(file, "{}", light)?;
+    }
+    Ok(path)
+}
+
+fn parse_light(line: &str) -> Option<Light> {
+    let mut words = line.split_whitespace();
+    let kind = LightKind::from_str(words.next()?).ok()?;
+    let mut v = [0.0; 11];
+    for x in &mut v {
+        *x = f32::from_str(words.next()?).ok()?;
+    }
+    let headlight = match words.next() {
+        None => false,
+        Some("headlight") => true,
+        Some(_) => return None,
+    };
+    Some(Light {
+        kind,
+        position: [v[0], v[1], v[2]],
+        direction: [v[3], v[4], v[5]],
+        color: [v[6], v[7], v[8]],
+        range: v[9],
+        angle: v[10],
+        headlight,
+    })
+}
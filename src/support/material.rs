@@ -0,0 +1,226 @@
+use glium::backend::Facade;
+use glium::Texture2d;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use support::texture::{TextureKind, TextureLoader};
+use support::tobj;
+
+/// Returns the opacity of the material from its dissolve (`d`) value, or one minus its
+/// transparency (`Tr`) for files which only give that
+pub fn opacity(material: &tobj::Material) -> f32 {
+    let transparency = material
+        .unknown_param
+        .get("Tr")
+        .and_then(|tr| tr.split_whitespace().next())
+        .and_then(|tr| f32::from_str(tr).ok());
+    let opacity = match transparency {
+        // tobj leaves dissolve at 1 if the material has no `d` statement
+        Some(tr) if material.dissolve == 1.0 => 1.0 - tr,
+        _ => material.dissolve,
+    };
+    opacity.clamp(0.0, 1.0)
+}
+
+/// The textures and constants of a model's material used when shading, on top of the
+/// diffuse and specular colors stored in its vertices
+#[derive(Clone, Default)]
+pub struct MaterialMaps {
+    /// Fragments where the mask is below one half are cut out of the model
+    pub alpha_mask: Option<Rc<Texture2d>>,
+    /// Tangent space normal map
+    pub normal_map: Option<Rc<Texture2d>>,
+    /// Multiplies the diffuse color (`map_Kd`)
+    pub diffuse_map: Option<Rc<Texture2d>>,
+    /// Multiplies the specular color (`map_Ks`)
+    pub specular_map: Option<Rc<Texture2d>>,
+    /// Multiplies the specular exponent (`map_Ns`)
+    pub shininess_map: Option<Rc<Texture2d>>,
+    /// Multiplies the emitted color (`map_Ke`)
+    pub emissive_map: Option<Rc<Texture2d>>,
+    /// Color emitted by the material (`Ke`)
+    pub emissive: [f32; 3],
+    /// Parameters for physically based shading, if the material uses the PBR extension
+    pub pbr: Option<Pbr>,
+}
+
+/// Metallic/roughness parameters from the PBR extension to MTL
+#[derive(Clone)]
+pub struct Pbr {
+    /// `Pr`, scaled by `map_Pr`
+    pub roughness: f32,
+    pub roughness_map: Option<Rc<Texture2d>>,
+    /// `Pm`, scaled by `map_Pm`
+    pub metallic: f32,
+    pub metallic_map: Option<Rc<Texture2d>>,
+    /// `Ps`
+    pub sheen: f32,
+    /// `Pc`, the strength of a clear coat layer over the base material
+    pub clearcoat: f32,
+    /// `Pcr`
+    pub clearcoat_roughness: f32,
+}
+
+/// Statements of the PBR extension, any of which switch a material to its own PBR values
+const PBR_PARAMS: [&str; 7] = ["Pr", "Pm", "map_Pr", "map_Pm", "Ps", "Pc", "Pcr"];
+
+/// Roughness with about the same highlight as a Blinn-Phong specular exponent
+fn phong_roughness(shininess: f32) -> f32 {
+    (2.0 / (shininess.max(1.0) + 2.0)).powf(0.25)
+}
+
+impl MaterialMaps {
+    pub fn load<F: Facade>(
+        facade: &F,
+        material: &tobj::Material,
+        textures: &mut TextureLoader,
+        warnings: &mut Vec<String>,
+    ) -> MaterialMaps {
+        let mut load = |name: &str, kind| {
+            if name.is_empty() {
+                None
+            } else {
+                textures.load(facade, name, kind, warnings)
+            }
+        };
+        let param = |key: &str| {
+            material
+                .unknown_param
+                .get(key)
+                .map(|s| s.as_str())
+                .unwrap_or("")
+        };
+        let diffuse_map = load(&material.diffuse_texture, TextureKind::Color);
+        let specular_map = load(&material.specular_texture, TextureKind::Color);
+        // tobj reads `map_Ns` into `normal_texture`
+        let shininess_map = load(&material.normal_texture, TextureKind::Scalar);
+        let emissive_map = load(param("map_Ke"), TextureKind::Color);
+        let emissive = match parse_color(param("Ke")) {
+            Some(ke) => ke,
+            // An emissive map without a color is taken to give the emitted color itself
+            None if emissive_map.is_some() => [1.0; 3],
+            None => [0.0; 3],
+        };
+        let pbr = if PBR_PARAMS
+            .iter()
+            .any(|p| material.unknown_param.contains_key(*p))
+        {
+            let number = |key: &str| f32::from_str(param(key).trim()).ok();
+            let roughness_map = load(param("map_Pr"), TextureKind::Scalar);
+            let metallic_map = load(param("map_Pm"), TextureKind::Scalar);
+            // Without a value the maps give it directly, and roughness otherwise
+            // follows the Phong shininess
+            Some(Pbr {
+                roughness: number("Pr").unwrap_or(if roughness_map.is_some() {
+                    1.0
+                } else {
+                    phong_roughness(material.shininess)
+                }),
+                roughness_map,
+                metallic: number("Pm").unwrap_or(if metallic_map.is_some() { 1.0 } else { 0.0 }),
+                metallic_map,
+                sheen: number("Ps").unwrap_or(0.0),
+                clearcoat: number("Pc").unwrap_or(0.0),
+                clearcoat_roughness: number("Pcr").unwrap_or(0.03),
+            })
+        } else {
+            None
+        };
+        MaterialMaps {
+            alpha_mask: alpha_mask(facade, material, textures, warnings),
+            normal_map: normal_map(facade, material, textures, warnings),
+            diffuse_map,
+            specular_map,
+            shininess_map,
+            emissive_map,
+            emissive,
+            pbr,
+        }
+    }
+
+    pub fn has_textures(&self) -> bool {
+        self.alpha_mask.is_some()
+            || self.normal_map.is_some()
+            || self.diffuse_map.is_some()
+            || self.specular_map.is_some()
+            || self.shininess_map.is_some()
+            || self.emissive_map.is_some()
+            || self
+                .pbr
+                .as_ref()
+                .map(|p| p.roughness_map.is_some() || p.metallic_map.is_some())
+                .unwrap_or(false)
+    }
+
+    /// Drop the textures, keeping the constant values
+    pub fn clear_textures(&mut self) {
+        let pbr = self.pbr.take().map(|pbr| Pbr {
+            roughness_map: None,
+            metallic_map: None,
+            ..pbr
+        });
+        *self = MaterialMaps {
+            emissive: self.emissive,
+            pbr,
+            ..Default::default()
+        };
+    }
+}
+
+/// Parse an MTL color given as one or three numbers
+fn parse_color(value: &str) -> Option<[f32; 3]> {
+    let values: Vec<f32> = value
+        .split_whitespace()
+        .map(f32::from_str)
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match values.len() {
+        1 => Some([values[0]; 3]),
+        3 => Some([values[0], values[1], values[2]]),
+        _ => None,
+    }
+}
+
+/// Returns the mask of the material's cut out parts, from its `map_d` texture or otherwise
+/// the alpha channel of its diffuse texture
+fn alpha_mask<F: Facade>(
+    facade: &F,
+    material: &tobj::Material,
+    textures: &mut TextureLoader,
+    warnings: &mut Vec<String>,
+) -> Option<Rc<Texture2d>> {
+    if !material.dissolve_texture.is_empty() {
+        textures.load(
+            facade,
+            &material.dissolve_texture,
+            TextureKind::Mask,
+            warnings,
+        )
+    } else if !material.diffuse_texture.is_empty() {
+        textures.load(
+            facade,
+            &material.diffuse_texture,
+            TextureKind::DiffuseAlpha,
+            warnings,
+        )
+    } else {
+        None
+    }
+}
+
+/// MTL statements naming a normal or bump map, tobj keeps them as unknown parameters
+const NORMAL_MAP_PARAMS: [&str; 4] = ["norm", "map_Bump", "map_bump", "bump"];
+
+/// Returns the material's tangent space normal map, from a `norm` or bump map statement
+fn normal_map<F: Facade>(
+    facade: &F,
+    material: &tobj::Material,
+    textures: &mut TextureLoader,
+    warnings: &mut Vec<String>,
+) -> Option<Rc<Texture2d>> {
+    NORMAL_MAP_PARAMS
+        .iter()
+        .filter_map(|p| material.unknown_param.get(*p))
+        .find(|name| !name.is_empty())
+        .and_then(|name| textures.load(facade, name, TextureKind::Normal, warnings))
+}
@@ -0,0 +1,256 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use support::edges::EdgeAdjacency;
+use support::json;
+use support::LoadOptions;
+
+/// Surface area, volume and centroid of a model in model units
+#[derive(Copy, Clone, Debug)]
+pub struct Measurements {
+    pub surface_area: f32,
+    /// Signed volume enclosed by the triangles, positive when they face outwards.
+    /// Only meaningful if the mesh is closed.
+    pub volume: f32,
+    /// The center of the enclosed volume for closed meshes, otherwise of the surface
+    pub centroid: [f32; 3],
+    /// The mesh has no boundary or non-manifold edges, so encloses a volume
+    pub closed: bool,
+}
+
+impl Measurements {
+    /// Returns the volume if the mesh is closed
+    pub fn closed_volume(&self) -> Option<f32> {
+        if self.closed {
+            Some(self.volume)
+        } else {
+            None
+        }
+    }
+}
+
+/// Measure the mesh as it's loaded with the options, the volume and centroid are found by
+/// summing the signed tetrahedra formed by each triangle and the origin. Triangles with out
+/// of range indices are skipped.
+pub fn measure(
+    positions: &[f32],
+    indices: &[u32],
+    adjacency: &EdgeAdjacency,
+    options: &LoadOptions,
+) -> Measurements {
+    let num_vertices = positions.len() / 3;
+    let position = |i: u32| {
+        let i = i as usize;
+        let p = options.orientation.apply([
+            positions[3 * i],
+            positions[3 * i + 1],
+            positions[3 * i + 2],
+        ]);
+        [f64::from(p[0]), f64::from(p[1]), f64::from(p[2])]
+    };
+    let mut area = 0.0;
+    let mut volume = 0.0;
+    let mut area_centroid = [0.0; 3];
+    let mut volume_centroid = [0.0; 3];
+    for face in indices.chunks(3) {
+        if face.len() < 3 || face.iter().any(|&i| i as usize >= num_vertices) {
+            continue;
+        }
+        let (a, b, c) = if options.flip_winding {
+            (position(face[0]), position(face[2]), position(face[1]))
+        } else {
+            (position(face[0]), position(face[1]), position(face[2]))
+        };
+        let e1 = sub(b, a);
+        let e2 = sub(c, a);
+        let n = cross(e1, e2);
+        let tri_area = 0.5 * dot(n, n).sqrt();
+        // Signed volume of the tetrahedron from the origin to the triangle
+        let tet_volume = dot(a, cross(b, c)) / 6.0;
+        area += tri_area;
+        volume += tet_volume;
+        for i in 0..3 {
+            area_centroid[i] += tri_area * (a[i] + b[i] + c[i]) / 3.0;
+            volume_centroid[i] += tet_volume * (a[i] + b[i] + c[i]) / 4.0;
+        }
+    }
+
+    let closed = !adjacency.edges().is_empty()
+        && adjacency
+            .edges()
+            .iter()
+            .all(|e| !e.is_boundary() && !e.is_non_manifold());
+    let centroid = if closed && volume.abs() > 0.0 {
+        [
+            volume_centroid[0] / volume,
+            volume_centroid[1] / volume,
+            volume_centroid[2] / volume,
+        ]
+    } else if area > 0.0 {
+        [
+            area_centroid[0] / area,
+            area_centroid[1] / area,
+            area_centroid[2] / area,
+        ]
+    } else {
+        [0.0; 3]
+    };
+    Measurements {
+        surface_area: area as f32,
+        volume: volume as f32,
+        centroid: [centroid[0] as f32, centroid[1] as f32, centroid[2] as f32],
+        closed,
+    }
+}
+
+/// Combine the measurements of several models, the centroid is weighted by volume
+/// if every model is closed and by surface area otherwise
+pub fn total(models: &[Measurements]) -> Measurements {
+    let closed = !models.is_empty() && models.iter().all(|m| m.closed);
+    let surface_area = models.iter().map(|m| m.surface_area).sum();
+    let volume = models.iter().map(|m| m.volume).sum();
+    let weight = |m: &Measurements| if closed { m.volume } else { m.surface_area };
+    let total_weight: f32 = models.iter().map(weight).sum();
+    let mut centroid = [0.0; 3];
+    if total_weight != 0.0 {
+        for m in models {
+            for (c, x) in centroid.iter_mut().zip(m.centroid.iter()) {
+                *c += x * weight(m) / total_weight;
+            }
+        }
+    }
+    Measurements {
+        surface_area,
+        volume,
+        centroid,
+        closed,
+    }
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn json_measurements(m: &Measurements) -> String {
+    let volume = match m.closed_volume() {
+        Some(v) => json::number(v),
+        None => "null".to_owned(),
+    };
+    format!(
+        "\"surface_area\": {}, \"volume\": {}, \"signed_volume\": {}, \
+         \"centroid\": [{}, {}, {}], \"closed\": {}",
+        json::number(m.surface_area),
+        volume,
+        json::number(m.volume),
+        json::number(m.centroid[0]),
+        json::number(m.centroid[1]),
+        json::number(m.centroid[2]),
+        m.closed
+    )
+}
+
+/// Write the measurements of each named model and their total as JSON, `volume` is null
+/// for models which aren't closed
+pub fn write_json<W: Write>(
+    w: &mut W,
+    model_file: &Path,
+    models: &[(String, Measurements)],
+) -> io::Result<()> {
+    let all: Vec<_> = models.iter().map(|&(_, m)| m).collect();
+    writeln!(w, "{{")?;
+    writeln!(
+        w,
+        "  \"file\": {},",
+        json::string(&model_file.to_string_lossy())
+    )?;
+    writeln!(w, "  \"models\": [")?;
+    for (i, (name, m)) in models.iter().enumerate() {
+        let separator = if i + 1 < models.len() { "," } else { "" };
+        writeln!(
+            w,
+            "    {{\"name\": {}, {}}}{}",
+            json::string(name),
+            json_measurements(m),
+            separator
+        )?;
+    }
+    writeln!(w, "  ],")?;
+    writeln!(w, "  \"total\": {{{}}}", json_measurements(&total(&all)))?;
+    writeln!(w, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use support::edges::tests::cube;
+
+    fn measure_mesh(positions: &[f32], indices: &[u32], options: &LoadOptions) -> Measurements {
+        let adjacency = EdgeAdjacency::new(positions, indices);
+        measure(positions, indices, &adjacency, options)
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn measures_a_closed_cube() {
+        let (positions, indices) = cube();
+        let m = measure_mesh(&positions, &indices, &LoadOptions::default());
+        assert!(m.closed);
+        assert_close(m.surface_area, 6.0);
+        assert_close(m.closed_volume().unwrap(), 1.0);
+        for &c in &m.centroid {
+            assert_close(c, 0.5);
+        }
+    }
+
+    #[test]
+    fn inside_out_cube_has_negative_volume() {
+        let (positions, indices) = cube();
+        let options = LoadOptions {
+            flip_winding: true,
+            ..Default::default()
+        };
+        let m = measure_mesh(&positions, &indices, &options);
+        assert_close(m.closed_volume().unwrap(), -1.0);
+    }
+
+    #[test]
+    fn open_cube_has_no_volume() {
+        let (positions, mut indices) = cube();
+        indices.truncate(indices.len() - 6);
+        let m = measure_mesh(&positions, &indices, &LoadOptions::default());
+        assert!(!m.closed);
+        assert!(m.closed_volume().is_none());
+        assert_close(m.surface_area, 5.0);
+        // The centroid of the five remaining faces
+        assert_close(m.centroid[2], 0.4);
+    }
+
+    #[test]
+    fn totals_weight_the_centroid_by_volume() {
+        let (positions, indices) = cube();
+        let unit = measure_mesh(&positions, &indices, &LoadOptions::default());
+        let scaled: Vec<f32> = positions.iter().map(|x| x * 2.0 + 1.0).collect();
+        let large = measure_mesh(&scaled, &indices, &LoadOptions::default());
+        let t = total(&[unit, large]);
+        assert!(t.closed);
+        assert_close(t.volume, 9.0);
+        assert_close(t.surface_area, 30.0);
+        // (1 * 0.5 + 8 * 2.0) / 9
+        assert_close(t.centroid[0], 16.5 / 9.0);
+    }
+}
@@ -3,14 +3,56 @@
 extern crate clock_ticks;
 extern crate tobj;
 
+use cgmath::Matrix4;
+use glium;
+use glium::backend::Facade;
 use glium::vertex::VertexBufferAny;
-use glium::{self, Display};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::f32;
+use std::fs::File;
+use std::io::BufReader;
+use std::mem;
+use std::ops::Range;
 use std::path::Path;
 use std::thread;
 use std::time::{Duration, Instant};
 
+pub mod bookmarks;
+pub mod bounds;
 pub mod camera;
+pub mod capture;
+pub mod clipping;
+pub mod edges;
+pub mod environment;
+pub mod explode;
+pub mod flythrough;
+pub mod json;
+pub mod lights;
+pub mod material;
+pub mod measure;
+pub mod oit;
+pub mod orientation;
+pub mod overlay;
+pub mod picking;
+pub mod render;
+pub mod shadow;
+pub mod stats;
+pub mod tangents;
+pub mod texture;
+pub mod validate;
+
+use self::bounds::Bounds;
+use self::clipping::ClipPlanes;
+use self::edges::EdgeAdjacency;
+use self::material::MaterialMaps;
+use self::measure::Measurements;
+use self::orientation::Orientation;
+use self::overlay::LineVertex;
+use self::picking::Hit;
+use self::stats::LoadTimes;
+use self::texture::TextureLoader;
+use self::validate::MeshReport;
 
 pub enum Action {
     Stop,
@@ -45,43 +87,359 @@ where
     }
 }
 
-/// Returns a vertex buffer that should be rendered as `TrianglesList`.
-pub fn load_wavefront(display: &Display, path: &Path) -> (VertexBufferAny, f32) {
-    #[derive(Copy, Clone)]
-    struct Vertex {
-        position: [f32; 3],
-        normal: [f32; 3],
-        color_diffuse: [f32; 3],
-        color_specular: [f32; 4],
+/// Bounds and statistics of a single `tobj::Model` within the loaded file.
+pub struct ModelInfo {
+    pub name: String,
+    pub bounds: Bounds,
+    pub num_vertices: usize,
+    pub num_indices: usize,
+    pub num_triangles: usize,
+    /// Name of the model's material, if it has one
+    pub material: Option<String>,
+    pub has_normals: bool,
+    pub has_texcoords: bool,
+    pub measurements: Measurements,
+    /// Edges used by only one triangle
+    pub num_boundary_edges: usize,
+    /// Edges shared by more than two triangles
+    pub num_non_manifold_edges: usize,
+    /// Loops of boundary edges around holes in the surface
+    pub num_holes: usize,
+}
+
+/// The draw data of a single model, `vertex_buffer` should be rendered as `TrianglesList`.
+pub struct ModelMesh {
+    pub vertex_buffer: VertexBufferAny,
+    /// The model's vertices in `Scene::triangles`
+    pub vertices: Range<usize>,
+    pub visible: bool,
+    /// Translation of the model in the exploded view, in model units
+    pub offset: [f32; 3],
+    /// Opacity of the model's material, from its dissolve value
+    pub opacity: f32,
+    pub material: MaterialMaps,
+}
+
+impl ModelMesh {
+    /// Transparent meshes are blended over the opaque ones
+    pub fn is_transparent(&self) -> bool {
+        self.opacity < 1.0
+    }
+}
+
+/// A loaded OBJ file, with one mesh for each entry in `models`
+pub struct Scene {
+    pub meshes: Vec<ModelMesh>,
+    /// Positions of the triangles of every model in model units, for picking
+    pub triangles: Vec<[f32; 3]>,
+    pub scale: f32,
+    pub bounds: Bounds,
+    pub models: Vec<ModelInfo>,
+    pub num_materials: usize,
+    /// Estimated size in bytes of the scene's buffers on the GPU
+    pub gpu_memory: usize,
+    /// Time taken to load the file and upload it to the GPU
+    pub load_times: LoadTimes,
+    /// Problems found in the geometry of each model, in the same order as `models`
+    pub validation: Vec<MeshReport>,
+    /// The boundary and non-manifold edges of each model, in the same order as `models`
+    pub open_edges: Vec<Vec<LineVertex>>,
+    /// Problems found while loading which didn't prevent the file from being displayed
+    pub warnings: Vec<String>,
+}
+
+/// Options controlling how an OBJ file is interpreted when loading it
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LoadOptions {
+    pub orientation: Orientation,
+    /// Reverse the winding order of every triangle
+    pub flip_winding: bool,
+}
+
+/// Uniform scale applied to the model positions when rendering
+pub const MODEL_SCALE: f32 = 0.005;
+
+impl Scene {
+    /// Returns the matrix placing the model in the world
+    pub fn model_matrix(&self) -> [[f32; 4]; 4] {
+        Matrix4::from_scale(MODEL_SCALE).into()
+    }
+
+    /// Returns the bounds of the scene in world space, including the exploded view's offsets
+    pub fn world_bounds(&self) -> Bounds {
+        let mut bounds = Bounds::empty();
+        for (m, mesh) in self.models.iter().zip(&self.meshes) {
+            bounds.union(&m.bounds.translated(mesh.offset));
+        }
+        bounds.scaled(MODEL_SCALE)
+    }
+
+    /// Push each model away from the scene's center along the direction to the center of
+    /// its bounds, by `amount` times its distance from the scene's center
+    pub fn set_explode(&mut self, amount: f32) {
+        let center = self.bounds.center();
+        for (m, mesh) in self.models.iter().zip(&mut self.meshes) {
+            let c = m.bounds.center();
+            mesh.offset = [
+                amount * (c[0] - center[0]),
+                amount * (c[1] - center[1]),
+                amount * (c[2] - center[2]),
+            ];
+        }
+    }
+
+    /// Find the point on the visible models hit by the ray, given in world space, ignoring
+    /// the parts cut away by the clip planes. Returns the hit point in model units and the
+    /// index of the model hit.
+    pub fn pick(&self, origin: [f32; 3], dir: [f32; 3], clip: &ClipPlanes) -> Option<(Hit, usize)> {
+        let origin = [
+            origin[0] / MODEL_SCALE,
+            origin[1] / MODEL_SCALE,
+            origin[2] / MODEL_SCALE,
+        ];
+        let mut closest: Option<(Hit, usize)> = None;
+        for (i, mesh) in self.meshes.iter().enumerate().filter(|&(_, m)| m.visible) {
+            let triangles = &self.triangles[mesh.vertices.clone()];
+            let local_origin = sub(origin, mesh.offset);
+            let keep = |p| clip.keeps(add(p, mesh.offset));
+            if let Some(mut hit) = picking::pick(triangles, local_origin, dir, keep) {
+                if closest
+                    .map(|(c, _)| hit.distance < c.distance)
+                    .unwrap_or(true)
+                {
+                    hit.point = add(hit.point, mesh.offset);
+                    hit.triangle += mesh.vertices.start / 3;
+                    closest = Some((hit, i));
+                }
+            }
+        }
+        closest
+    }
+
+    /// Returns the vertex of the triangle hit on the model closest to the hit point
+    pub fn snap_to_vertex(&self, hit: &Hit, model: usize) -> [f32; 3] {
+        let offset = self.meshes[model].offset;
+        let local = Hit {
+            point: sub(hit.point, offset),
+            ..*hit
+        };
+        add(picking::snap_to_vertex(&self.triangles, &local), offset)
+    }
+
+    /// Returns the models in the same OBJ group or object as the model. tobj splits
+    /// groups using several materials into models with the same name.
+    pub fn group(&self, model: usize) -> Vec<usize> {
+        let name = &self.models[model].name;
+        (0..self.models.len())
+            .filter(|&i| self.models[i].name == *name)
+            .collect()
+    }
+
+    /// Hide the models
+    pub fn hide(&mut self, models: &[usize]) {
+        for &i in models {
+            self.meshes[i].visible = false;
+        }
+    }
+
+    /// Hide every model except these ones, which are shown
+    pub fn isolate(&mut self, models: &[usize]) {
+        for (i, mesh) in self.meshes.iter_mut().enumerate() {
+            mesh.visible = models.contains(&i);
+        }
+    }
+
+    pub fn show_all(&mut self) {
+        for mesh in &mut self.meshes {
+            mesh.visible = true;
+        }
+    }
+
+    /// Returns the meshes of the visible models which have any triangles to draw
+    pub fn visible_meshes<'a>(&'a self) -> impl Iterator<Item = &'a ModelMesh> + 'a {
+        self.meshes
+            .iter()
+            .filter(|m| m.visible && !m.vertices.is_empty())
+    }
+
+    pub fn num_visible(&self) -> usize {
+        self.meshes.iter().filter(|m| m.visible).count()
+    }
+
+    pub fn num_triangles(&self) -> usize {
+        self.models.iter().map(|m| m.num_triangles).sum()
     }
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+/// Load the OBJ file, a material library which fails to load is reported as a
+/// warning and its materials are rendered with the default material instead.
+pub fn load_obj(path: &Path, warnings: &mut Vec<String>) -> tobj::LoadResult {
+    let file = File::open(path).map_err(|_| tobj::LoadError::OpenFileFailed)?;
+    let mtl_warnings = RefCell::new(Vec::new());
+    let result = tobj::load_obj_buf(&mut BufReader::new(file), |mat_path| {
+        let full_path = match path.parent() {
+            Some(parent) => parent.join(mat_path),
+            None => mat_path.to_owned(),
+        };
+        match tobj::load_mtl(&full_path) {
+            Ok(m) => Ok(m),
+            Err(e) => {
+                mtl_warnings.borrow_mut().push(format!(
+                    "Failed to load material library {}: {}",
+                    full_path.display(),
+                    e
+                ));
+                Ok((Vec::new(), HashMap::new()))
+            }
+        }
+    });
+    warnings.append(&mut mtl_warnings.into_inner());
+    result
+}
+
+/// A vertex of the model meshes
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    texcoord: [f32; 2],
+    tangent: [f32; 4],
+    color_diffuse: [f32; 3],
+    color_specular: [f32; 4],
+}
+
+// glium's `implement_vertex!` uses the deprecated `mem::uninitialized`
+#[allow(deprecated)]
+mod vertex_impl {
+    use super::Vertex;
 
-    implement_vertex!(Vertex, position, normal, color_diffuse, color_specular);
+    implement_vertex!(
+        Vertex,
+        position,
+        normal,
+        texcoord,
+        tangent,
+        color_diffuse,
+        color_specular
+    );
+}
+
+pub fn load_wavefront<F: Facade>(
+    display: &F,
+    path: &Path,
+    options: &LoadOptions,
+) -> Result<Scene, tobj::LoadError> {
+    let start = Instant::now();
 
-    let mut min_pos = [f32::INFINITY; 3];
-    let mut max_pos = [f32::NEG_INFINITY; 3];
+    /// A model's vertices and material, waiting to be uploaded
+    struct MeshData {
+        vertices: Range<usize>,
+        opacity: f32,
+        material: MaterialMaps,
+    }
+
+    let mut bounds = Bounds::empty();
+    let mut models_info = Vec::new();
+    let mut validation = Vec::new();
+    let mut open_edges = Vec::new();
     let mut vertex_data = Vec::new();
-    match tobj::load_obj(path) {
+    let mut model_vertices = Vec::new();
+    let mut textures = TextureLoader::new(path);
+    let mut warnings = Vec::new();
+    let num_materials;
+    let parse_time;
+    match load_obj(path, &mut warnings) {
         Ok((models, mats)) => {
-            // Just upload the first object in the group
+            parse_time = start.elapsed();
+            num_materials = mats.len();
             for model in &models {
                 let mesh = &model.mesh;
-                println!("Uploading model: {}", model.name);
-                for idx in &mesh.indices {
-                    let i = *idx as usize;
-                    let pos = [
+                let adjacency = EdgeAdjacency::new(&mesh.positions, &mesh.indices);
+                let report =
+                    validate::check_mesh(&model.name, mesh, &adjacency, &options.orientation);
+                let valid_indices = report
+                    .issues
+                    .iter()
+                    .all(|i| i.kind != validate::IssueKind::IndexOutOfRange);
+                if !valid_indices {
+                    warnings.push(format!(
+                        "Model {} has out of range indices and was skipped",
+                        model.name
+                    ));
+                    continue;
+                }
+                validation.push(report);
+                if mesh.indices.is_empty() {
+                    warnings.push(format!("Model {} has no faces", model.name));
+                } else if mesh.normals.is_empty() {
+                    warnings.push(format!("Model {} has no normals", model.name));
+                }
+                let material = mesh.material_id.map(|i| &mats[i]);
+                let mut maps = material
+                    .map(|m| MaterialMaps::load(display, m, &mut textures, &mut warnings))
+                    .unwrap_or_default();
+                if mesh.texcoords.is_empty() && maps.has_textures() {
+                    warnings.push(format!(
+                        "Model {} has textures but no texture coordinates, they won't be used",
+                        model.name
+                    ));
+                    maps.clear_textures();
+                }
+                let tangents = if maps.normal_map.is_some() && !mesh.normals.is_empty() {
+                    let vec3 = |v: &[f32]| options.orientation.apply([v[0], v[1], v[2]]);
+                    let positions: Vec<_> = mesh.positions.chunks(3).map(vec3).collect();
+                    let normals: Vec<_> = mesh.normals.chunks(3).map(vec3).collect();
+                    let texcoords: Vec<_> =
+                        mesh.texcoords.chunks(2).map(|t| [t[0], t[1]]).collect();
+                    tangents::compute_tangents(&positions, &normals, &texcoords, &mesh.indices)
+                } else {
+                    Vec::new()
+                };
+                let mut model_bounds = Bounds::empty();
+                let first_vertex = vertex_data.len();
+                let winding = if options.flip_winding {
+                    [0, 2, 1]
+                } else {
+                    [0, 1, 2]
+                };
+                let face_indices = mesh
+                    .indices
+                    .chunks(3)
+                    .flat_map(|face| winding.iter().map(move |&v| face[v]));
+                for idx in face_indices {
+                    let i = idx as usize;
+                    let pos = options.orientation.apply([
                         mesh.positions[3 * i],
                         mesh.positions[3 * i + 1],
                         mesh.positions[3 * i + 2],
-                    ];
+                    ]);
                     let normal = if !mesh.normals.is_empty() {
-                        [
+                        options.orientation.apply([
                             mesh.normals[3 * i],
                             mesh.normals[3 * i + 1],
                             mesh.normals[3 * i + 2],
-                        ]
+                        ])
                     } else {
                         [0.0, 0.0, 0.0]
                     };
+                    let texcoord = if !mesh.texcoords.is_empty() {
+                        [mesh.texcoords[2 * i], mesh.texcoords[2 * i + 1]]
+                    } else {
+                        [0.0, 0.0]
+                    };
+                    let tangent = if !tangents.is_empty() {
+                        tangents[i]
+                    } else {
+                        [0.0; 4]
+                    };
                     let (color_diffuse, color_specular) = match mesh.material_id {
                         Some(i) => (
                             mats[i].diffuse,
@@ -96,32 +454,123 @@ pub fn load_wavefront(display: &Display, path: &Path) -> (VertexBufferAny, f32)
                     };
                     vertex_data.push(Vertex {
                         position: pos,
-                        normal: normal,
-                        color_diffuse: color_diffuse,
-                        color_specular: color_specular,
+                        normal,
+                        texcoord,
+                        tangent,
+                        color_diffuse,
+                        color_specular,
                     });
                     // Update our min/max pos so we can figure out the bounding box of the object
                     // to view it
-                    for i in 0..3 {
-                        min_pos[i] = f32::min(min_pos[i], pos[i]);
-                        max_pos[i] = f32::max(max_pos[i], pos[i]);
-                    }
+                    model_bounds.extend(pos);
                 }
+                let edge_position = |i: u32| {
+                    let i = i as usize;
+                    options.orientation.apply([
+                        mesh.positions[3 * i],
+                        mesh.positions[3 * i + 1],
+                        mesh.positions[3 * i + 2],
+                    ])
+                };
+                let mut model_edges = Vec::new();
+                for e in adjacency.edges() {
+                    let kind = if e.is_boundary() {
+                        validate::IssueKind::BoundaryEdge
+                    } else if e.is_non_manifold() {
+                        validate::IssueKind::NonManifoldEdge
+                    } else {
+                        continue;
+                    };
+                    validate::push_line(
+                        &mut model_edges,
+                        edge_position(e.a),
+                        edge_position(e.b),
+                        kind,
+                    );
+                }
+                open_edges.push(model_edges);
+                bounds.union(&model_bounds);
+                let opacity = mesh
+                    .material_id
+                    .map(|i| material::opacity(&mats[i]))
+                    .unwrap_or(1.0);
+                model_vertices.push(MeshData {
+                    vertices: first_vertex..vertex_data.len(),
+                    opacity,
+                    material: maps,
+                });
+                models_info.push(ModelInfo {
+                    name: model.name.clone(),
+                    bounds: model_bounds,
+                    num_vertices: mesh.positions.len() / 3,
+                    num_indices: mesh.indices.len(),
+                    num_triangles: mesh.indices.len() / 3,
+                    material: mesh.material_id.map(|i| mats[i].name.clone()),
+                    has_normals: !mesh.normals.is_empty(),
+                    has_texcoords: !mesh.texcoords.is_empty(),
+                    measurements: measure::measure(
+                        &mesh.positions,
+                        &mesh.indices,
+                        &adjacency,
+                        options,
+                    ),
+                    num_boundary_edges: adjacency.boundary_edges().count(),
+                    num_non_manifold_edges: adjacency.non_manifold_edges().count(),
+                    num_holes: adjacency.num_holes(),
+                });
             }
         }
-        Err(e) => panic!("Loading of {:?} failed due to {:?}", path, e),
+        Err(e) => return Err(e),
     }
+    println!(
+        "Loaded as {}{}",
+        options.orientation,
+        if options.flip_winding {
+            " with flipped winding"
+        } else {
+            ""
+        }
+    );
     // Compute scale factor to fit the model with a [-1, 1] bounding box
     let diagonal_len = 6.0;
-    let current_len = f32::powf(max_pos[0] - min_pos[0], 2.0)
-        + f32::powf(max_pos[1] - min_pos[1], 2.0)
-        + f32::powf(max_pos[2] - min_pos[2], 2.0);
+    let current_len = f32::powf(bounds.diagonal(), 2.0);
     let scale = f32::sqrt(diagonal_len / current_len);
     println!("Model scaled by {} to fit", scale);
-    (
-        glium::vertex::VertexBuffer::new(display, &vertex_data)
+    for w in &warnings {
+        println!("Warning: {}", w);
+    }
+    let process_time = start.elapsed() - parse_time;
+    let meshes = model_vertices
+        .into_iter()
+        .map(|data| ModelMesh {
+            vertex_buffer: glium::vertex::VertexBuffer::new(
+                display,
+                &vertex_data[data.vertices.clone()],
+            )
             .unwrap()
             .into_vertex_buffer_any(),
+            vertices: data.vertices,
+            visible: true,
+            offset: [0.0; 3],
+            opacity: data.opacity,
+            material: data.material,
+        })
+        .collect();
+    Ok(Scene {
+        meshes,
+        triangles: vertex_data.iter().map(|v| v.position).collect(),
         scale,
-    )
+        bounds,
+        models: models_info,
+        num_materials,
+        gpu_memory: vertex_data.len() * mem::size_of::<Vertex>() + textures.gpu_memory,
+        load_times: LoadTimes {
+            parse: parse_time,
+            process: process_time,
+            upload: start.elapsed() - parse_time - process_time,
+        },
+        validation,
+        open_edges,
+        warnings,
+    })
 }
@@ -0,0 +1,227 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use glium::backend::{Context, Facade};
+use glium::framebuffer::MultiOutputFrameBuffer;
+use glium::texture::{
+    DepthFormat, DepthTexture2d, MipmapsOption, Texture2d, UncompressedFloatFormat,
+};
+use glium::{
+    self, Api, Blend, BlendingFunction, LinearBlendingFactor, Program, Surface, Version,
+    VertexBuffer,
+};
+
+use support::render::{MESH_SHADING_140, MESH_VERTEX_140};
+
+#[derive(Copy, Clone)]
+struct QuadVertex {
+    position: [f32; 2],
+}
+
+// glium's `implement_vertex!` uses the deprecated `mem::uninitialized`
+#[allow(deprecated)]
+mod quad_vertex_impl {
+    use super::QuadVertex;
+
+    implement_vertex!(QuadVertex, position);
+}
+
+/// The offscreen targets the transparent surfaces are accumulated into
+struct Targets {
+    size: (u32, u32),
+    /// Sum of the weighted premultiplied colors, with the product of one minus the
+    /// alpha of each surface (the revealage) in the alpha channel
+    accum: Texture2d,
+    /// Sum of the weighted alphas
+    weight: Texture2d,
+    depth: DepthTexture2d,
+}
+
+impl Targets {
+    fn new(context: &Rc<Context>, size: (u32, u32)) -> Targets {
+        let color = |format| {
+            Texture2d::empty_with_format(context, format, MipmapsOption::NoMipmap, size.0, size.1)
+                .unwrap()
+        };
+        Targets {
+            size,
+            accum: color(UncompressedFloatFormat::F16F16F16F16),
+            weight: color(UncompressedFloatFormat::F16),
+            depth: DepthTexture2d::empty_with_format(
+                context,
+                DepthFormat::F32,
+                MipmapsOption::NoMipmap,
+                size.0,
+                size.1,
+            )
+            .unwrap(),
+        }
+    }
+}
+
+/// Weighted blended order independent transparency (McGuire and Bavoil 2013). Transparent
+/// surfaces are accumulated in any order into offscreen targets with a weight falling off
+/// with depth, then composited over the opaque scene in one pass. Only available with
+/// GLSL 1.40, which the accumulation program needs for its multiple outputs.
+pub struct WeightedBlended {
+    context: Rc<Context>,
+    /// Shades the transparent meshes into the accumulation targets, with the same
+    /// uniforms as the mesh program
+    pub program: Program,
+    composite_program: Program,
+    quad: VertexBuffer<QuadVertex>,
+    targets: RefCell<Option<Targets>>,
+}
+
+impl WeightedBlended {
+    /// Returns None if the context doesn't support GLSL 1.40
+    pub fn new<F: Facade>(facade: &F) -> Option<WeightedBlended> {
+        let context = facade.get_context();
+        if !context.is_glsl_version_supported(&Version(Api::Gl, 1, 4)) {
+            return None;
+        }
+        let quad = [
+            QuadVertex {
+                position: [-1.0, -1.0],
+            },
+            QuadVertex {
+                position: [1.0, -1.0],
+            },
+            QuadVertex {
+                position: [-1.0, 1.0],
+            },
+            QuadVertex {
+                position: [1.0, 1.0],
+            },
+        ];
+        Some(WeightedBlended {
+            context: context.clone(),
+            program: accum_program(facade),
+            composite_program: composite_program(facade),
+            quad: VertexBuffer::new(facade, &quad).unwrap(),
+            targets: RefCell::new(None),
+        })
+    }
+
+    /// Returns the blending which sums the weighted colors and multiplies the revealage
+    pub fn accum_blend() -> Blend {
+        Blend {
+            color: BlendingFunction::Addition {
+                source: LinearBlendingFactor::One,
+                destination: LinearBlendingFactor::One,
+            },
+            alpha: BlendingFunction::Addition {
+                source: LinearBlendingFactor::Zero,
+                destination: LinearBlendingFactor::OneMinusSourceAlpha,
+            },
+            constant_value: (0.0, 0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Clear the accumulation targets to the size of `target`, call `draw` to render the
+    /// opaque depth and the transparent surfaces into them with `program`, then composite
+    /// the result over `target`
+    pub fn render<S, F>(&self, target: &mut S, draw: F)
+    where
+        S: Surface,
+        F: FnOnce(&mut MultiOutputFrameBuffer),
+    {
+        let size = target.get_dimensions();
+        let mut targets = self.targets.borrow_mut();
+        if targets.as_ref().map(|t| t.size != size).unwrap_or(true) {
+            *targets = Some(Targets::new(&self.context, size));
+        }
+        let targets = targets.as_ref().unwrap();
+        {
+            let outputs = [("accum", &targets.accum), ("weight", &targets.weight)];
+            let mut framebuffer = MultiOutputFrameBuffer::with_depth_buffer(
+                &self.context,
+                outputs.iter().cloned(),
+                &targets.depth,
+            )
+            .unwrap();
+            // The accumulated revealage starts at one, the weight target's alpha is unused
+            framebuffer.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0);
+            draw(&mut framebuffer);
+        }
+
+        let uniforms = uniform! {
+            accum_texture: &targets.accum,
+            weight_texture: &targets.weight,
+        };
+        let params = glium::DrawParameters {
+            blend: Blend::alpha_blending(),
+            ..Default::default()
+        };
+        target
+            .draw(
+                &self.quad,
+                glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
+                &self.composite_program,
+                &uniforms,
+                &params,
+            )
+            .unwrap();
+    }
+}
+
+fn accum_program<F: Facade>(facade: &F) -> Program {
+    let fragment = format!(
+        "#version 140\n{}{}",
+        MESH_SHADING_140,
+        "
+            out vec4 accum;
+            out vec4 weight;
+
+            void main() {
+                vec4 color = shade();
+                // Weight nearer surfaces more, equation 9 of the paper
+                float w = clamp(pow(min(1.0, color.a * 10.0) + 0.01, 3.0) * 1e8
+                                * pow(1.0 - gl_FragCoord.z * 0.9, 3.0), 1e-2, 3e3);
+                accum = vec4(color.rgb * color.a * w, color.a);
+                weight = vec4(color.a * w, 0.0, 0.0, 0.0);
+            }
+        "
+    );
+    program!(facade,
+        140 => {
+            vertex: MESH_VERTEX_140,
+            fragment: &fragment,
+        },
+    )
+    .unwrap()
+}
+
+fn composite_program<F: Facade>(facade: &F) -> Program {
+    program!(facade,
+        140 => {
+            vertex: "
+                #version 140
+
+                in vec2 position;
+
+                void main() {
+                    gl_Position = vec4(position, 0.0, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 140
+
+                uniform sampler2D accum_texture;
+                uniform sampler2D weight_texture;
+
+                out vec4 f_color;
+
+                void main() {
+                    ivec2 texel = ivec2(gl_FragCoord.xy);
+                    vec4 accum = texelFetch(accum_texture, texel, 0);
+                    float weight = texelFetch(weight_texture, texel, 0).r;
+                    vec3 color = accum.rgb / max(weight, 1e-5);
+                    f_color = vec4(color, 1.0 - accum.a);
+                }
+            ",
+        },
+    )
+    .unwrap()
+}
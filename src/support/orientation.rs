@@ -0,0 +1,78 @@
+use std::fmt;
+
+/// The axis the file treats as up
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Handedness {
+    Right,
+    Left,
+}
+
+/// How to interpret the coordinate system of an OBJ file. The viewer works in a Y-up
+/// right-handed space, so files authored in other conventions are converted at load time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Orientation {
+    pub up: UpAxis,
+    pub handedness: Handedness,
+}
+
+impl Orientation {
+    pub fn new(up: UpAxis, handedness: Handedness) -> Orientation {
+        Orientation { up, handedness }
+    }
+
+    /// Returns the row-major matrix converting positions and normals from this
+    /// orientation to the viewer's Y-up right-handed space.
+    pub fn matrix(&self) -> [[f32; 3]; 3] {
+        match (self.up, self.handedness) {
+            (UpAxis::Y, Handedness::Right) => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            (UpAxis::Y, Handedness::Left) => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]],
+            (UpAxis::Z, Handedness::Right) => [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]],
+            (UpAxis::Z, Handedness::Left) => [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]],
+        }
+    }
+
+    pub fn apply(&self, v: [f32; 3]) -> [f32; 3] {
+        let m = self.matrix();
+        [
+            m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+            m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+            m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+        ]
+    }
+
+    /// Cycle to the next interpretation: Y-up RH, Z-up RH, Y-up LH, Z-up LH
+    pub fn next(&self) -> Orientation {
+        match (self.up, self.handedness) {
+            (UpAxis::Y, Handedness::Right) => Orientation::new(UpAxis::Z, Handedness::Right),
+            (UpAxis::Z, Handedness::Right) => Orientation::new(UpAxis::Y, Handedness::Left),
+            (UpAxis::Y, Handedness::Left) => Orientation::new(UpAxis::Z, Handedness::Left),
+            (UpAxis::Z, Handedness::Left) => Orientation::new(UpAxis::Y, Handedness::Right),
+        }
+    }
+}
+
+impl Default for Orientation {
+    fn default() -> Orientation {
+        Orientation::new(UpAxis::Y, Handedness::Right)
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let up = match self.up {
+            UpAxis::Y => "Y-up",
+            UpAxis::Z => "Z-up",
+        };
+        let handedness = match self.handedness {
+            Handedness::Right => "right-handed",
+            Handedness::Left => "left-handed",
+        };
+        write!(f, "{} {}", up, handedness)
+    }
+}
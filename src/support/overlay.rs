@@ -0,0 +1,577 @@
+use cgmath::{Matrix4, Vector4};
+use glium::backend::Facade;
+use glium::{self, Program, Surface, VertexBuffer};
+use std::f32;
+
+use support::bounds::Bounds;
+use support::camera::CameraState;
+use support::Scene;
+
+#[derive(Copy, Clone)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+// glium's `implement_vertex!` uses the deprecated `mem::uninitialized`
+#[allow(deprecated)]
+mod line_vertex_impl {
+    use super::LineVertex;
+
+    implement_vertex!(LineVertex, position, color);
+}
+
+const MODEL_BOUNDS_COLOR: [f32; 3] = [0.9, 0.7, 0.1];
+const SCENE_BOUNDS_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+const GRID_COLOR: [f32; 3] = [0.35, 0.35, 0.35];
+const GRID_CENTER_COLOR: [f32; 3] = [0.6, 0.6, 0.6];
+const SELECTION_COLOR: [f32; 3] = [0.2, 0.8, 1.0];
+const LABEL_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+/// Pixels per unit of the label glyphs, which are two units tall
+const LABEL_SCALE: f32 = 7.0;
+/// Distance in pixels from the labeled point to the lower left corner of the label
+const LABEL_OFFSET: f32 = 10.0;
+
+/// The segments of a seven segment display as start and end points in a glyph one unit
+/// wide and two units tall, in the order top, upper right, lower right, bottom, lower left,
+/// upper left and middle
+const SEGMENTS: [([f32; 2], [f32; 2]); 7] = [
+    ([0.0, 2.0], [1.0, 2.0]),
+    ([1.0, 2.0], [1.0, 1.0]),
+    ([1.0, 1.0], [1.0, 0.0]),
+    ([0.0, 0.0], [1.0, 0.0]),
+    ([0.0, 0.0], [0.0, 1.0]),
+    ([0.0, 1.0], [0.0, 2.0]),
+    ([0.0, 1.0], [1.0, 1.0]),
+];
+/// The segments lit for each digit, with the top segment in the lowest bit
+const DIGIT_SEGMENTS: [u8; 10] = [
+    0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110, 0b1101101, 0b1111101, 0b0000111,
+    0b1111111, 0b1101111,
+];
+
+/// Line overlays drawn on top of the model: the per-model and scene bounding boxes,
+/// a ground grid on the XZ plane, the boundary and non-manifold edges, highlights of
+/// validation issues, the distance measurement and its label, the bounds of the selected model
+/// and an RGB axis gizmo in the lower left corner.
+pub struct Overlays {
+    program: Program,
+    model_bounds: VertexBuffer<LineVertex>,
+    scene_bounds: VertexBuffer<LineVertex>,
+    grid: VertexBuffer<LineVertex>,
+    axes: VertexBuffer<LineVertex>,
+    issues: VertexBuffer<LineVertex>,
+    open_edges: VertexBuffer<LineVertex>,
+    ruler: VertexBuffer<LineVertex>,
+    /// The glyphs of the measured distance in glyph units, drawn next to the point in model
+    /// units they label
+    label: VertexBuffer<LineVertex>,
+    label_point: Option<[f32; 3]>,
+    selection: VertexBuffer<LineVertex>,
+    /// The spacing of the ground grid lines in model units
+    pub grid_step: f32,
+    pub show_bounds: bool,
+    pub show_grid: bool,
+    pub show_axes: bool,
+    /// Highlight the geometry with validation issues, drawn through the model
+    pub show_issues: bool,
+    /// Draw the boundary and non-manifold edges of the model
+    pub show_open_edges: bool,
+}
+
+impl Overlays {
+    pub fn new<F: Facade>(display: &F, scene: &Scene) -> Overlays {
+        let program = line_program(display);
+        let axes = VertexBuffer::new(display, &axes_lines()).unwrap();
+        let (grid, grid_step) = grid_lines(&scene.bounds);
+        let mut overlays = Overlays {
+            program,
+            model_bounds: VertexBuffer::new(display, &[]).unwrap(),
+            scene_bounds: VertexBuffer::new(display, &box_lines(&scene.bounds, SCENE_BOUNDS_COLOR))
+                .unwrap(),
+            grid: VertexBuffer::new(display, &grid).unwrap(),
+            axes,
+            issues: VertexBuffer::new(display, &[]).unwrap(),
+            open_edges: VertexBuffer::new(display, &[]).unwrap(),
+            ruler: VertexBuffer::new(display, &[]).unwrap(),
+            label: VertexBuffer::new(display, &[]).unwrap(),
+            label_point: None,
+            selection: VertexBuffer::new(display, &[]).unwrap(),
+            grid_step,
+            show_bounds: false,
+            show_grid: false,
+            show_axes: true,
+            show_issues: false,
+            show_open_edges: false,
+        };
+        overlays.set_models(display, scene);
+        overlays
+    }
+
+    /// Rebuild the bounds and grid geometry for a newly loaded scene
+    pub fn set_scene<F: Facade>(&mut self, display: &F, scene: &Scene) {
+        let (grid, grid_step) = grid_lines(&scene.bounds);
+        self.scene_bounds =
+            VertexBuffer::new(display, &box_lines(&scene.bounds, SCENE_BOUNDS_COLOR)).unwrap();
+        self.grid = VertexBuffer::new(display, &grid).unwrap();
+        self.set_models(display, scene);
+        self.grid_step = grid_step;
+        self.ruler = VertexBuffer::new(display, &[]).unwrap();
+        self.label = VertexBuffer::new(display, &[]).unwrap();
+        self.label_point = None;
+        self.selection = VertexBuffer::new(display, &[]).unwrap();
+    }
+
+    /// Rebuild the bounds, open edges and issue highlights of the visible models at their
+    /// offsets in the exploded view
+    pub fn set_models<F: Facade>(&mut self, display: &F, scene: &Scene) {
+        let bounds = model_lines(scene, |i| {
+            box_lines(&scene.models[i].bounds, MODEL_BOUNDS_COLOR)
+        });
+        self.model_bounds = VertexBuffer::new(display, &bounds).unwrap();
+        let issues = model_lines(scene, |i| scene.validation[i].highlights.clone());
+        self.issues = VertexBuffer::new(display, &issues).unwrap();
+        let open_edges = model_lines(scene, |i| scene.open_edges[i].clone());
+        self.open_edges = VertexBuffer::new(display, &open_edges).unwrap();
+    }
+
+    /// Set the lines showing the distance measurement, in model units, and the text
+    /// labeling it at a point
+    pub fn set_ruler<F: Facade>(
+        &mut self,
+        display: &F,
+        lines: &[LineVertex],
+        label: Option<([f32; 3], String)>,
+    ) {
+        self.ruler = VertexBuffer::new(display, lines).unwrap();
+        let glyphs = label
+            .as_ref()
+            .map(|(_, text)| text_lines(text, LABEL_COLOR))
+            .unwrap_or_default();
+        self.label = VertexBuffer::new(display, &glyphs).unwrap();
+        self.label_point = label.map(|(point, _)| point);
+    }
+
+    /// Outline the bounds of the selected models
+    pub fn set_selection<F: Facade>(&mut self, display: &F, scene: &Scene, models: &[usize]) {
+        let lines: Vec<_> = models
+            .iter()
+            .flat_map(|&i| {
+                let bounds = scene.models[i].bounds.translated(scene.meshes[i].offset);
+                box_lines(&bounds, SELECTION_COLOR)
+            })
+            .collect();
+        self.selection = VertexBuffer::new(display, &lines).unwrap();
+    }
+
+    pub fn draw<S: Surface>(
+        &self,
+        target: &mut S,
+        camera: &CameraState,
+        model_matrix: [[f32; 4]; 4],
+    ) {
+        let view_matrix = camera.get_view();
+        let uniforms = uniform! {
+            persp_matrix: camera.get_projection(),
+            view_matrix: view_matrix,
+            model_matrix: model_matrix,
+        };
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::DepthTest::IfLessOrEqual,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let lines = glium::index::NoIndices(glium::index::PrimitiveType::LinesList);
+        if self.show_grid {
+            target
+                .draw(&self.grid, lines, &self.program, &uniforms, &params)
+                .unwrap();
+        }
+        if self.show_bounds {
+            target
+                .draw(&self.model_bounds, lines, &self.program, &uniforms, &params)
+                .unwrap();
+            target
+                .draw(&self.scene_bounds, lines, &self.program, &uniforms, &params)
+                .unwrap();
+        }
+        if self.show_open_edges && self.open_edges.len() > 0 {
+            target
+                .draw(&self.open_edges, lines, &self.program, &uniforms, &params)
+                .unwrap();
+        }
+        if self.show_issues && self.issues.len() > 0 {
+            // Skip the depth test so issues hidden inside or behind the model are visible
+            target
+                .draw(
+                    &self.issues,
+                    lines,
+                    &self.program,
+                    &uniforms,
+                    &Default::default(),
+                )
+                .unwrap();
+        }
+        if self.selection.len() > 0 {
+            target
+                .draw(
+                    &self.selection,
+                    lines,
+                    &self.program,
+                    &uniforms,
+                    &Default::default(),
+                )
+                .unwrap();
+        }
+        if self.ruler.len() > 0 {
+            target
+                .draw(
+                    &self.ruler,
+                    lines,
+                    &self.program,
+                    &uniforms,
+                    &Default::default(),
+                )
+                .unwrap();
+        }
+        if let Some(point) = self.label_point {
+            self.draw_label(target, camera, model_matrix, point);
+        }
+        if self.show_axes {
+            self.draw_axes(target, view_matrix);
+        }
+    }
+
+    /// Draw the label at a constant size on screen, up and to the right of where the point
+    /// projects to. It's drawn over the model like the ruler it labels.
+    fn draw_label<S: Surface>(
+        &self,
+        target: &mut S,
+        camera: &CameraState,
+        model_matrix: [[f32; 4]; 4],
+        point: [f32; 3],
+    ) {
+        let view_proj = Matrix4::from(camera.get_projection())
+            * Matrix4::from(camera.get_view())
+            * Matrix4::from(model_matrix);
+        let clip = view_proj * Vector4::new(point[0], point[1], point[2], 1.0);
+        // Behind the camera
+        if clip.w <= 0.0 {
+            return;
+        }
+        let (width, height) = target.get_dimensions();
+        let (sx, sy) = (2.0 / width as f32, 2.0 / height as f32);
+        let identity = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0f32],
+        ];
+        let uniforms = uniform! {
+            persp_matrix: [
+                [LABEL_SCALE * sx, 0.0, 0.0, 0.0],
+                [0.0, LABEL_SCALE * sy, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [
+                    clip.x / clip.w + LABEL_OFFSET * sx,
+                    clip.y / clip.w + LABEL_OFFSET * sy,
+                    0.0,
+                    1.0f32,
+                ],
+            ],
+            view_matrix: identity,
+            model_matrix: identity,
+        };
+        target
+            .draw(
+                &self.label,
+                glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+                &self.program,
+                &uniforms,
+                &Default::default(),
+            )
+            .unwrap();
+    }
+
+    /// Draw the axis gizmo in its own viewport in the lower left corner, using only
+    /// the rotation of the camera so it shows the orientation of the world axes.
+    fn draw_axes<S: Surface>(&self, target: &mut S, view_matrix: [[f32; 4]; 4]) {
+        let (width, height) = target.get_dimensions();
+        let size = u32::max(u32::min(width, height) / 6, 48);
+        let mut rotation = view_matrix;
+        rotation[3] = [0.0, 0.0, 0.0, 1.0];
+        let uniforms = uniform! {
+            persp_matrix: [
+                [0.8, 0.0, 0.0, 0.0],
+                [0.0, 0.8, 0.0, 0.0],
+                [0.0, 0.0, 0.8, 0.0],
+                [0.0, 0.0, 0.0, 1.0f32],
+            ],
+            view_matrix: rotation,
+            model_matrix: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0f32],
+            ],
+        };
+        let params = glium::DrawParameters {
+            viewport: Some(glium::Rect {
+                left: 8,
+                bottom: 8,
+                width: size,
+                height: size,
+            }),
+            ..Default::default()
+        };
+        target
+            .draw(
+                &self.axes,
+                glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+                &self.program,
+                &uniforms,
+                &params,
+            )
+            .unwrap();
+    }
+}
+
+/// Returns the 12 edges of the box as a line list
+pub fn box_lines(bounds: &Bounds, color: [f32; 3]) -> Vec<LineVertex> {
+    let mut lines = Vec::new();
+    if bounds.is_empty() {
+        return lines;
+    }
+    let corners = bounds.corners();
+    for c in 0..8 {
+        for i in 0..3 {
+            if c & (1 << i) == 0 {
+                lines.push(LineVertex {
+                    position: corners[c],
+                    color,
+                });
+                lines.push(LineVertex {
+                    position: corners[c | (1 << i)],
+                    color,
+                });
+            }
+        }
+    }
+    lines
+}
+
+/// Collects the lines of the visible models, moved by each model's offset in the exploded view
+fn model_lines<F>(scene: &Scene, lines: F) -> Vec<LineVertex>
+where
+    F: Fn(usize) -> Vec<LineVertex>,
+{
+    let mut all = Vec::new();
+    for (i, mesh) in scene.meshes.iter().enumerate().filter(|(_, m)| m.visible) {
+        let o = mesh.offset;
+        all.extend(lines(i).into_iter().map(|v| LineVertex {
+            position: [
+                v.position[0] + o[0],
+                v.position[1] + o[1],
+                v.position[2] + o[2],
+            ],
+            color: v.color,
+        }));
+    }
+    all
+}
+
+/// Build a grid on the XZ plane at the bottom of the bounds. The spacing is the power of
+/// ten giving roughly 10-100 lines across the model, so the grid also shows the units
+/// the model was authored in. Returns the lines and the grid spacing.
+fn grid_lines(bounds: &Bounds) -> (Vec<LineVertex>, f32) {
+    let mut lines = Vec::new();
+    if bounds.is_empty() {
+        return (lines, 1.0);
+    }
+    let size = bounds.diagonal() * 1.5;
+    let size = if size > 0.0 { size } else { 1.0 };
+    let step = f32::powf(10.0, f32::floor(f32::log10(size / 10.0)));
+    let n = f32::ceil(0.5 * size / step) as i32;
+    let center = bounds.center();
+    let cx = f32::round(center[0] / step) * step;
+    let cz = f32::round(center[2] / step) * step;
+    let y = bounds.min[1];
+    let half = n as f32 * step;
+    for i in -n..=n {
+        let color = if i == 0 {
+            GRID_CENTER_COLOR
+        } else {
+            GRID_COLOR
+        };
+        let offset = i as f32 * step;
+        lines.push(LineVertex {
+            position: [cx + offset, y, cz - half],
+            color,
+        });
+        lines.push(LineVertex {
+            position: [cx + offset, y, cz + half],
+            color,
+        });
+        lines.push(LineVertex {
+            position: [cx - half, y, cz + offset],
+            color,
+        });
+        lines.push(LineVertex {
+            position: [cx + half, y, cz + offset],
+            color,
+        });
+    }
+    (lines, step)
+}
+
+/// Returns the digits, decimal points and minus signs of the text as seven segment glyphs
+/// starting at the origin, other characters are left as spaces
+fn text_lines(text: &str, color: [f32; 3]) -> Vec<LineVertex> {
+    let mut lines = Vec::new();
+    let mut x = 0.0;
+    let mut segment = |x: f32, (a, b): ([f32; 2], [f32; 2])| {
+        lines.push(LineVertex {
+            position: [x + a[0], a[1], 0.0],
+            color,
+        });
+        lines.push(LineVertex {
+            position: [x + b[0], b[1], 0.0],
+            color,
+        });
+    };
+    for c in text.chars() {
+        match c {
+            '0'..='9' => {
+                let lit = DIGIT_SEGMENTS[c as usize - '0' as usize];
+                for (i, &s) in SEGMENTS.iter().enumerate() {
+                    if lit & (1 << i) != 0 {
+                        segment(x, s);
+                    }
+                }
+            }
+            '.' => {
+                segment(x, ([0.1, 0.0], [0.1, 0.2]));
+                x -= 0.8;
+            }
+            '-' => segment(x, SEGMENTS[6]),
+            _ => {}
+        }
+        x += 1.5;
+    }
+    lines
+}
+
+fn axes_lines() -> Vec<LineVertex> {
+    let mut lines = Vec::new();
+    for i in 0..3 {
+        let mut end = [0.0; 3];
+        end[i] = 1.0;
+        let mut color = [0.0; 3];
+        color[i] = 1.0;
+        lines.push(LineVertex {
+            position: [0.0, 0.0, 0.0],
+            color,
+        });
+        lines.push(LineVertex {
+            position: end,
+            color,
+        });
+    }
+    lines
+}
+
+fn line_program<F: Facade>(display: &F) -> Program {
+    program!(display,
+        140 => {
+            vertex: "
+                #version 140
+
+                uniform mat4 persp_matrix;
+                uniform mat4 view_matrix;
+                uniform mat4 model_matrix;
+
+                in vec3 position;
+                in vec3 color;
+                out vec3 v_color;
+
+                void main() {
+                    v_color = color;
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 140
+
+                in vec3 v_color;
+                out vec4 f_color;
+
+                void main() {
+                    f_color = vec4(v_color, 1.0);
+                }
+            ",
+        },
+
+        110 => {
+            vertex: "
+                #version 110
+
+                uniform mat4 persp_matrix;
+                uniform mat4 view_matrix;
+                uniform mat4 model_matrix;
+
+                attribute vec3 position;
+                attribute vec3 color;
+                varying vec3 v_color;
+
+                void main() {
+                    v_color = color;
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 110
+
+                varying vec3 v_color;
+
+                void main() {
+                    gl_FragColor = vec4(v_color, 1.0);
+                }
+            ",
+        },
+
+        100 => {
+            vertex: "
+                #version 100
+
+                uniform lowp mat4 persp_matrix;
+                uniform lowp mat4 view_matrix;
+                uniform lowp mat4 model_matrix;
+
+                attribute lowp vec3 position;
+                attribute lowp vec3 color;
+                varying lowp vec3 v_color;
+
+                void main() {
+                    v_color = color;
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 100
+
+                varying lowp vec3 v_color;
+
+                void main() {
+                    gl_FragColor = vec4(v_color, 1.0);
+                }
+            ",
+        },
+    )
+    .unwrap()
+}
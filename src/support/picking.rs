@@ -0,0 +1,169 @@
+use cgmath::{InnerSpace, Vector3};
+
+use support::overlay::LineVertex;
+
+/// Triangles whose determinant is below this are treated as parallel to the ray
+const PARALLEL_EPSILON: f32 = 1e-12;
+/// Size of the crosses marking the ruler's end points relative to the scene's diagonal
+const MARKER_SIZE: f32 = 0.01;
+const RULER_COLOR: [f32; 3] = [1.0, 0.2, 0.2];
+
+/// The closest point on the model under the cursor
+#[derive(Copy, Clone, Debug)]
+pub struct Hit {
+    pub point: [f32; 3],
+    /// Index of the triangle hit
+    pub triangle: usize,
+    /// Distance along the ray to the hit point
+    pub distance: f32,
+}
+
+/// Find the closest triangle hit by the ray, `triangles` is a triangle list of positions.
+/// Both sides of the triangles can be hit, hit points which `keep` rejects are skipped.
+pub fn pick<F>(triangles: &[[f32; 3]], origin: [f32; 3], dir: [f32; 3], keep: F) -> Option<Hit>
+where
+    F: Fn([f32; 3]) -> bool,
+{
+    let origin = Vector3::from(origin);
+    let dir = Vector3::from(dir);
+    let mut closest: Option<Hit> = None;
+    for (i, tri) in triangles.chunks(3).enumerate() {
+        if tri.len() < 3 {
+            break;
+        }
+        // Möller-Trumbore intersection
+        let v0 = Vector3::from(tri[0]);
+        let e1 = Vector3::from(tri[1]) - v0;
+        let e2 = Vector3::from(tri[2]) - v0;
+        let p = dir.cross(e2);
+        let det = e1.dot(p);
+        if det.abs() < PARALLEL_EPSILON {
+            continue;
+        }
+        let inv_det = 1.0 / det;
+        let t_vec = origin - v0;
+        let u = t_vec.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            continue;
+        }
+        let q = t_vec.cross(e1);
+        let v = dir.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            continue;
+        }
+        let t = e2.dot(q) * inv_det;
+        if t <= 0.0 || closest.map(|c| t >= c.distance).unwrap_or(false) {
+            continue;
+        }
+        let point = (origin + dir * t).into();
+        if !keep(point) {
+            continue;
+        }
+        closest = Some(Hit {
+            point,
+            triangle: i,
+            distance: t,
+        });
+    }
+    closest
+}
+
+/// Returns the vertex of the hit triangle closest to the hit point
+pub fn snap_to_vertex(triangles: &[[f32; 3]], hit: &Hit) -> [f32; 3] {
+    let point = Vector3::from(hit.point);
+    triangles[3 * hit.triangle..3 * hit.triangle + 3]
+        .iter()
+        .cloned()
+        .min_by(|a, b| {
+            let da = (Vector3::from(*a) - point).magnitude2();
+            let db = (Vector3::from(*b) - point).magnitude2();
+            da.partial_cmp(&db).unwrap()
+        })
+        .unwrap()
+}
+
+/// Measures the distance between two points picked on the model
+pub struct Ruler {
+    points: Vec<[f32; 3]>,
+    /// Snap picked points to the closest vertex of the triangle under the cursor
+    pub snap: bool,
+}
+
+impl Ruler {
+    pub fn new() -> Ruler {
+        Ruler {
+            points: Vec::new(),
+            snap: false,
+        }
+    }
+
+    /// Add an end point, starting a new measurement if both are already set
+    pub fn add(&mut self, point: [f32; 3]) {
+        if self.points.len() == 2 {
+            self.points.clear();
+        }
+        self.points.push(point);
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    pub fn points(&self) -> &[[f32; 3]] {
+        &self.points
+    }
+
+    /// Returns the distance between the end points once both are picked
+    pub fn distance(&self) -> Option<f32> {
+        if self.points.len() == 2 {
+            Some((Vector3::from(self.points[1]) - Vector3::from(self.points[0])).magnitude())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the midpoint of the line between the end points and the distance to label
+    /// it with, once both are picked
+    pub fn label(&self) -> Option<([f32; 3], String)> {
+        let d = self.distance()?;
+        let (a, b) = (self.points[0], self.points[1]);
+        let midpoint = [
+            0.5 * (a[0] + b[0]),
+            0.5 * (a[1] + b[1]),
+            0.5 * (a[2] + b[2]),
+        ];
+        Some((midpoint, format!("{:.4}", d)))
+    }
+
+    /// Returns crosses marking the end points and the line between them, `scale` sets
+    /// the size of the crosses
+    pub fn lines(&self, scale: f32) -> Vec<LineVertex> {
+        let size = MARKER_SIZE * scale;
+        let mut lines = Vec::new();
+        for p in &self.points {
+            for axis in 0..3 {
+                let mut a = *p;
+                let mut b = *p;
+                a[axis] -= size;
+                b[axis] += size;
+                lines.push(LineVertex {
+                    position: a,
+                    color: RULER_COLOR,
+                });
+                lines.push(LineVertex {
+                    position: b,
+                    color: RULER_COLOR,
+                });
+            }
+        }
+        if self.points.len() == 2 {
+            for p in &self.points {
+                lines.push(LineVertex {
+                    position: *p,
+                    color: RULER_COLOR,
+                });
+            }
+        }
+        lines
+    }
+}
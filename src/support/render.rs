@@ -0,0 +1,1291 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector4};
+use glium::backend::Facade;
+use glium::draw_parameters::{Stencil, StencilOperation, StencilTest};
+use glium::texture::{ClientFormat, RawImage2d};
+use glium::{self, Program, Surface, Texture2d, VertexBuffer};
+use std::borrow::Cow;
+
+use support::camera::CameraState;
+use support::clipping::{ClipPlanes, MAX_CLIP_PLANES};
+use support::environment::{self, Environment, Skybox};
+use support::lights::{self, Light, LightKind};
+use support::material::Pbr;
+use support::oit::WeightedBlended;
+use support::overlay::{LineVertex, Overlays};
+use support::shadow::{self, ShadowMap};
+use support::{ModelMesh, Scene, MODEL_SCALE};
+
+/// Brightness of the surroundings without an environment map
+const AMBIENT: f32 = 0.3;
+
+/// Plane equation which keeps everything, used for disabled clip planes
+const NO_CLIP: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// Draws a loaded scene along with its overlays
+pub struct Renderer {
+    program: Program,
+    cap_program: Program,
+    clip_planes: [[f32; 4]; MAX_CLIP_PLANES],
+    /// The quads capping the cut through the model for each enabled clip plane
+    caps: Vec<(usize, VertexBuffer<LineVertex>)>,
+    /// White texture bound in place of missing material textures, which leaves the mask,
+    /// colors and shininess they'd scale unchanged
+    white: Texture2d,
+    /// Normal map bound for meshes without one, which keeps the vertex normals
+    flat_normal_map: Texture2d,
+    /// The lights shading the model, up to `MAX_LIGHTS`
+    pub lights: Vec<Light>,
+    /// Perturb the normals of meshes with normal or bump maps
+    pub normal_mapping: bool,
+    /// Shade with the metallic/roughness GGX model instead of Blinn-Phong
+    pub physically_based: bool,
+    /// Lights the model when no environment map is loaded, with the same ambient light
+    /// from every direction
+    constant_environment: Environment,
+    environment: Option<Rc<Environment>>,
+    skybox: Skybox,
+    /// Draw the environment map as the background
+    pub show_skybox: bool,
+    /// Shadow map of the key light, if the context can render one
+    shadow_map: Option<ShadowMap>,
+    /// Cast shadows from the key light, the first directional light, when supported
+    pub shadows: bool,
+    /// Order independent transparency, if the context supports it
+    oit: Option<WeightedBlended>,
+    /// Draw transparent meshes with weighted blended order independent transparency
+    /// instead of sorting them, when supported
+    pub order_independent: bool,
+    pub overlays: Overlays,
+}
+
+impl Renderer {
+    pub fn new<F: Facade>(facade: &F, scene: &Scene) -> Renderer {
+        Renderer {
+            program: mesh_program(facade),
+            cap_program: cap_program(facade),
+            clip_planes: [NO_CLIP; MAX_CLIP_PLANES],
+            caps: Vec::new(),
+            white: Texture2d::new(
+                facade,
+                RawImage2d {
+                    data: Cow::Owned(vec![255u8, 255, 255]),
+                    width: 1,
+                    height: 1,
+                    format: ClientFormat::U8U8U8,
+                },
+            )
+            .unwrap(),
+            flat_normal_map: Texture2d::new(
+                facade,
+                RawImage2d {
+                    data: Cow::Owned(vec![128u8, 128, 255]),
+                    width: 1,
+                    height: 1,
+                    format: ClientFormat::U8U8U8,
+                },
+            )
+            .unwrap(),
+            lights: vec![Light::key()],
+            normal_mapping: true,
+            physically_based: false,
+            constant_environment: Environment::constant(facade, AMBIENT),
+            environment: None,
+            skybox: Skybox::new(facade),
+            show_skybox: true,
+            shadow_map: ShadowMap::new(facade, scene),
+            shadows: true,
+            oit: WeightedBlended::new(facade),
+            order_independent: false,
+            overlays: Overlays::new(facade, scene),
+        }
+    }
+
+    pub fn supports_order_independent(&self) -> bool {
+        self.oit.is_some()
+    }
+
+    /// Light the model with an environment map instead of the constant ambient light
+    pub fn set_environment(&mut self, environment: Rc<Environment>) {
+        self.environment = Some(environment);
+    }
+
+    pub fn supports_shadows(&self) -> bool {
+        self.shadow_map.is_some()
+    }
+
+    pub fn has_skybox(&self) -> bool {
+        self.environment
+            .as_ref()
+            .map(|e| e.has_sky())
+            .unwrap_or(false)
+    }
+
+    /// Rebuild any scene dependent data for a newly loaded scene
+    pub fn set_scene<F: Facade>(&mut self, facade: &F, scene: &Scene) {
+        self.overlays.set_scene(facade, scene);
+        if let Some(ref mut shadow_map) = self.shadow_map {
+            shadow_map.set_scene(facade, scene);
+        }
+    }
+
+    /// Set the section planes cutting through the model and rebuild their caps
+    pub fn set_clip_planes<F: Facade>(&mut self, facade: &F, planes: &ClipPlanes) {
+        self.clip_planes = planes.equations();
+        self.caps = planes
+            .planes
+            .iter()
+            .enumerate()
+            .filter(|&(_, p)| p.enabled)
+            .map(|(i, _)| (i, VertexBuffer::new(facade, &planes.cap_quad(i)).unwrap()))
+            .collect();
+    }
+
+    /// Clear the target and draw the scene from the camera's viewpoint
+    pub fn draw<S: Surface>(&self, target: &mut S, camera: &CameraState, scene: &Scene) {
+        let model_matrix = scene.model_matrix();
+        let persp_matrix = camera.get_projection();
+        let view_matrix = camera.get_view();
+
+        // The camera position and view direction in the model's space, for the specular
+        // highlights and headlights. The camera looks down +z in view space.
+        let inv_model_view = (Matrix4::from(view_matrix) * Matrix4::from(model_matrix))
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        let eye = inv_model_view * Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let eye_position = [eye.x / eye.w, eye.y / eye.w, eye.z / eye.w];
+        let view_dir = (inv_model_view * Vector4::new(0.0, 0.0, 1.0, 0.0))
+            .truncate()
+            .normalize();
+        let mut light = lights::uniforms(&self.lights, eye_position, view_dir.into());
+
+        // Render the shadow map of the key light with its frustum fitted to the model,
+        // including the offsets of the exploded view
+        let bounds = scene.world_bounds().scaled(1.0 / MODEL_SCALE);
+        let key_light = self
+            .lights
+            .iter()
+            .position(|l| l.kind == LightKind::Directional);
+        let shadow_map = match (self.shadow_map.as_ref(), key_light) {
+            (Some(shadow_map), Some(key)) if self.shadows && !bounds.is_empty() => {
+                let to_light = light[key][0];
+                let light_matrix =
+                    shadow::light_matrix([to_light[0], to_light[1], to_light[2]], &bounds);
+                shadow_map.render(scene, light_matrix, &self.clip_planes, &self.white);
+                light[key][3][0] = 1.0;
+                Some((shadow_map, light_matrix))
+            }
+            _ => None,
+        };
+        let shadow_matrix = shadow_map
+            .map(|(_, m)| m)
+            .unwrap_or_else(|| Matrix4::identity().into());
+
+        let environment = self
+            .environment
+            .as_deref()
+            .unwrap_or(&self.constant_environment);
+
+        // building the uniforms, each model is drawn at its offset in the exploded view
+        let uniforms = |mesh| {
+            // Annotated here rather than on the argument so the uniforms can borrow the
+            // mesh's texture for as long as the scene
+            let mesh: &ModelMesh = mesh;
+            let material = &mesh.material;
+            let normal_map = match material.normal_map {
+                Some(ref map) if self.normal_mapping => &**map,
+                _ => &self.flat_normal_map,
+            };
+            // Materials without PBR values have their roughness derived from the shininess
+            // in the shader, where it includes map_Ns
+            let pbr = material.pbr.as_ref();
+            let pbr_value = |value: fn(&Pbr) -> f32, default| pbr.map(value).unwrap_or(default);
+            uniform! {
+                persp_matrix: persp_matrix,
+                view_matrix: view_matrix,
+                model_matrix: model_matrix,
+                model_offset: mesh.offset,
+                opacity: mesh.opacity,
+                eye_position: eye_position,
+                light0: light[0],
+                light1: light[1],
+                light2: light[2],
+                light3: light[3],
+                alpha_mask: material.alpha_mask.as_deref().unwrap_or(&self.white),
+                normal_map: normal_map,
+                diffuse_map: material.diffuse_map.as_deref().unwrap_or(&self.white),
+                specular_map: material.specular_map.as_deref().unwrap_or(&self.white),
+                shininess_map: material.shininess_map.as_deref().unwrap_or(&self.white),
+                emissive_map: material.emissive_map.as_deref().unwrap_or(&self.white),
+                emissive: material.emissive,
+                physically_based: self.physically_based,
+                pbr_material: pbr.is_some(),
+                roughness: pbr_value(|p| p.roughness, 1.0),
+                metallic: pbr_value(|p| p.metallic, 0.0),
+                sheen: pbr_value(|p| p.sheen, 0.0),
+                clearcoat: pbr_value(|p| p.clearcoat, 0.0),
+                clearcoat_roughness: pbr_value(|p| p.clearcoat_roughness, 0.03),
+                roughness_map: pbr
+                    .and_then(|p| p.roughness_map.as_deref())
+                    .unwrap_or(&self.white),
+                environment_irradiance: environment::sampler(&environment.irradiance, false),
+                environment_specular: environment::sampler(&environment.specular, true),
+                environment_levels: environment.max_level,
+                metallic_map: pbr
+                    .and_then(|p| p.metallic_map.as_deref())
+                    .unwrap_or(&self.white),
+                shadow_matrix: shadow_matrix,
+                shadow_map: shadow_map
+                    .map(|(s, _)| s.sampler())
+                    .unwrap_or_else(|| environment::sampler(&self.white, false)),
+                shadow_texel: shadow_map.map(|(s, _)| s.texel_size()).unwrap_or(1.0),
+                clip_plane0: self.clip_planes[0],
+                clip_plane1: self.clip_planes[1],
+                clip_plane2: self.clip_planes[2],
+            }
+        };
+
+        // draw parameters
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let triangles = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        if self.caps.is_empty() {
+            target.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+        } else {
+            target.clear_all((0.0, 0.0, 0.0, 0.0), 1.0, 0);
+        }
+        if self.show_skybox {
+            let inv_view_proj = (Matrix4::from(persp_matrix) * Matrix4::from(view_matrix))
+                .invert()
+                .map(Into::into);
+            if let Some(inv_view_proj) = inv_view_proj {
+                self.skybox.draw(target, environment, inv_view_proj);
+            }
+        }
+        for mesh in scene.visible_meshes().filter(|m| !m.is_transparent()) {
+            target
+                .draw(
+                    &mesh.vertex_buffer,
+                    triangles,
+                    &self.program,
+                    &uniforms(mesh),
+                    &params,
+                )
+                .unwrap();
+        }
+        // The ground under the grid catches the model's shadow, showing where it touches
+        if let Some((shadow_map, light_matrix)) = shadow_map {
+            if self.overlays.show_grid {
+                shadow_map.draw_ground(
+                    target,
+                    [persp_matrix, view_matrix, model_matrix],
+                    light_matrix,
+                    &params,
+                );
+            }
+        }
+
+        // Cap the cut through the model for each plane. The depth test is off, so every
+        // surface left by the clip planes along the ray through a pixel toggles its stencil
+        // value. Where the model is cut open the ray crosses an odd number of the surfaces of
+        // a closed mesh and the cap is drawn.
+        let stencil_params = glium::DrawParameters {
+            color_mask: (false, false, false, false),
+            stencil: Stencil {
+                depth_pass_operation_clockwise: StencilOperation::Invert,
+                depth_pass_operation_counter_clockwise: StencilOperation::Invert,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let cap_params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            stencil: Stencil {
+                test_clockwise: StencilTest::IfEqual { mask: 1 },
+                reference_value_clockwise: 1,
+                test_counter_clockwise: StencilTest::IfEqual { mask: 1 },
+                reference_value_counter_clockwise: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for &(plane, ref cap) in &self.caps {
+            target.clear_stencil(0);
+            for mesh in scene.visible_meshes() {
+                target
+                    .draw(
+                        &mesh.vertex_buffer,
+                        triangles,
+                        &self.program,
+                        &uniforms(mesh),
+                        &stencil_params,
+                    )
+                    .unwrap();
+            }
+            // The cap lies on its own plane, so it's only clipped by the others
+            let mut cap_planes = self.clip_planes;
+            cap_planes[plane] = NO_CLIP;
+            let cap_uniforms = uniform! {
+                persp_matrix: persp_matrix,
+                view_matrix: view_matrix,
+                model_matrix: model_matrix,
+                clip_plane0: cap_planes[0],
+                clip_plane1: cap_planes[1],
+                clip_plane2: cap_planes[2],
+            };
+            target
+                .draw(
+                    cap,
+                    triangles,
+                    &self.cap_program,
+                    &cap_uniforms,
+                    &cap_params,
+                )
+                .unwrap();
+        }
+
+        let any_transparent = scene.visible_meshes().any(|m| m.is_transparent());
+        match self.oit {
+            Some(ref oit) if self.order_independent && any_transparent => {
+                oit.render(target, |framebuffer| {
+                    // Fill the accumulation depth with the opaque meshes so they hide
+                    // the transparent surfaces behind them
+                    let depth_params = glium::DrawParameters {
+                        color_mask: (false, false, false, false),
+                        ..params.clone()
+                    };
+                    let accum_params = glium::DrawParameters {
+                        depth: glium::Depth {
+                            test: glium::DepthTest::IfLess,
+                            write: false,
+                            ..Default::default()
+                        },
+                        blend: WeightedBlended::accum_blend(),
+                        ..Default::default()
+                    };
+                    for mesh in scene.visible_meshes() {
+                        let params = if mesh.is_transparent() {
+                            &accum_params
+                        } else {
+                            &depth_params
+                        };
+                        framebuffer
+                            .draw(
+                                &mesh.vertex_buffer,
+                                triangles,
+                                &oit.program,
+                                &uniforms(mesh),
+                                params,
+                            )
+                            .unwrap();
+                    }
+                });
+            }
+            _ => {
+                // Blend the transparent meshes over the opaque ones from back to front,
+                // testing against but not writing depth so they don't hide each other
+                let blend_params = glium::DrawParameters {
+                    depth: glium::Depth {
+                        test: glium::DepthTest::IfLess,
+                        write: false,
+                        ..Default::default()
+                    },
+                    blend: glium::Blend::alpha_blending(),
+                    ..Default::default()
+                };
+                for mesh in sort_back_to_front(scene, view_matrix) {
+                    target
+                        .draw(
+                            &mesh.vertex_buffer,
+                            triangles,
+                            &self.program,
+                            &uniforms(mesh),
+                            &blend_params,
+                        )
+                        .unwrap();
+                }
+            }
+        }
+        self.overlays.draw(target, camera, model_matrix);
+    }
+}
+
+/// Returns the visible transparent meshes sorted from the farthest to the nearest by the
+/// view space depth of the centers of their bounds
+fn sort_back_to_front(scene: &Scene, view_matrix: [[f32; 4]; 4]) -> Vec<&ModelMesh> {
+    let mut meshes: Vec<_> = scene
+        .models
+        .iter()
+        .zip(&scene.meshes)
+        .filter(|&(_, mesh)| mesh.visible && mesh.is_transparent() && !mesh.vertices.is_empty())
+        .map(|(m, mesh)| {
+            let c = m.bounds.center();
+            let p = [
+                MODEL_SCALE * (c[0] + mesh.offset[0]),
+                MODEL_SCALE * (c[1] + mesh.offset[1]),
+                MODEL_SCALE * (c[2] + mesh.offset[2]),
+            ];
+            // The camera looks down +z, so larger depths are farther away
+            let depth = view_matrix[0][2] * p[0]
+                + view_matrix[1][2] * p[1]
+                + view_matrix[2][2] * p[2]
+                + view_matrix[3][2];
+            (depth, mesh)
+        })
+        .collect();
+    meshes.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+    meshes.into_iter().map(|(_, mesh)| mesh).collect()
+}
+
+/// The GLSL 140 vertex shader of the meshes, also used by the order independent
+/// transparency pass
+pub const MESH_VERTEX_140: &str = "
+    #version 140
+
+    uniform mat4 persp_matrix;
+    uniform mat4 view_matrix;
+    uniform mat4 model_matrix;
+    uniform vec3 model_offset;
+
+    in vec3 position;
+    in vec3 normal;
+    in vec2 texcoord;
+    in vec4 tangent;
+    in vec3 color_diffuse;
+    in vec4 color_specular;
+    out vec3 v_position;
+    out vec3 v_normal;
+    out vec2 v_texcoord;
+    out vec4 v_tangent;
+    out vec3 v_color_diffuse;
+    out vec4 v_color_specular;
+
+    void main() {
+        v_position = position + model_offset;
+        v_normal = normal;
+        v_texcoord = texcoord;
+        v_tangent = tangent;
+        v_color_diffuse = color_diffuse;
+        v_color_specular = color_specular;
+        gl_Position = persp_matrix * view_matrix * model_matrix * vec4(v_position, 1.0);
+    }
+";
+
+/// The GLSL 140 material and lighting of the meshes, ending with `shade` which returns the
+/// color and opacity of the fragment. The programs drawing the meshes follow the version
+/// with this and then their own outputs and `main`, so they're all shaded the same way.
+pub const MESH_SHADING_140: &str = "
+    uniform vec4 clip_plane0;
+    uniform vec4 clip_plane1;
+    uniform vec4 clip_plane2;
+    uniform float opacity;
+    uniform vec3 eye_position;
+    uniform mat4 light0;
+    uniform mat4 light1;
+    uniform mat4 light2;
+    uniform mat4 light3;
+    uniform sampler2D alpha_mask;
+    uniform sampler2D normal_map;
+    uniform sampler2D diffuse_map;
+    uniform sampler2D specular_map;
+    uniform sampler2D shininess_map;
+    uniform sampler2D emissive_map;
+    uniform vec3 emissive;
+    uniform bool physically_based;
+    uniform bool pbr_material;
+    uniform float roughness;
+    uniform float metallic;
+    uniform float sheen;
+    uniform float clearcoat;
+    uniform float clearcoat_roughness;
+    uniform sampler2D roughness_map;
+    uniform sampler2D metallic_map;
+    uniform sampler2D environment_irradiance;
+    uniform sampler2D environment_specular;
+    uniform float environment_levels;
+    uniform mat4 shadow_matrix;
+    uniform sampler2D shadow_map;
+    uniform float shadow_texel;
+
+    in vec3 v_position;
+    in vec3 v_normal;
+    in vec2 v_texcoord;
+    in vec4 v_tangent;
+    in vec3 v_color_diffuse;
+    in vec4 v_color_specular;
+
+    const float PI = 3.14159265;
+
+    vec2 equirect(vec3 d) {
+        return vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+    }
+
+    vec3 irradiance(vec3 n) {
+        return texture(environment_irradiance, equirect(n)).rgb;
+    }
+
+    // Environment light reflected by a surface of the given roughness
+    vec3 radiance(vec3 d, float r) {
+        return textureLod(environment_specular, equirect(d), r * environment_levels).rgb;
+    }
+
+    // Analytic fit of the GGX reflectance integrated over the environment (Karis 2014)
+    vec3 environment_brdf(vec3 f0, float r, float nv) {
+        vec4 c = r * vec4(-1.0, -0.0275, -0.572, 0.022) + vec4(1.0, 0.0425, 1.04, -0.04);
+        float a = min(c.x * c.x, exp2(-9.28 * nv)) * c.x + c.y;
+        return f0 * (a * -1.04 + c.z) + (a * 1.04 + c.w);
+    }
+
+    vec3 phong_ambient(vec3 n, vec3 v, vec3 kd, vec3 ks, float ns) {
+        return kd * irradiance(n) + ks * radiance(reflect(-v, n), pow(2.0 / (ns + 2.0), 0.25));
+    }
+
+    vec3 phong(vec3 n, vec3 v, vec3 l, vec3 kd, vec3 ks, float ns) {
+        float lum = max(dot(n, l), 0.0);
+        float spec = lum > 0.0 ? pow(max(dot(n, normalize(l + v)), 0.0), ns) : 0.0;
+        return kd * lum + ks * spec;
+    }
+
+    // GGX distribution with alpha = r * r
+    float ggx(float nh, float r) {
+        float a2 = r * r * r * r;
+        float d = nh * nh * (a2 - 1.0) + 1.0;
+        return a2 / (PI * d * d);
+    }
+
+    // Smith-Schlick masking and shadowing divided by 4 (n.l) (n.v)
+    float visibility(float nl, float nv, float r) {
+        float k = (r + 1.0) * (r + 1.0) / 8.0;
+        return 0.25 / ((nl * (1.0 - k) + k) * (nv * (1.0 - k) + k));
+    }
+
+    vec3 ggx_ambient(vec3 n, vec3 v, vec3 base, float r, float m) {
+        r = clamp(r, 0.03, 1.0);
+        vec3 f0 = mix(vec3(0.04), base, m);
+        return (1.0 - m) * base * irradiance(n)
+            + radiance(reflect(-v, n), r) * environment_brdf(f0, r, max(dot(n, v), 1e-4));
+    }
+
+    // Metallic/roughness GGX with a clear coat lobe and Disney sheen, scaled by pi so a white
+    // diffuse surface is as bright as with the Phong shading
+    vec3 shade_ggx(vec3 n, vec3 v, vec3 l, vec3 base, float r, float m) {
+        r = clamp(r, 0.03, 1.0);
+        vec3 h = normalize(l + v);
+        float nl = max(dot(n, l), 0.0);
+        float nv = max(dot(n, v), 1e-4);
+        float nh = max(dot(n, h), 0.0);
+        float fh = pow(1.0 - max(dot(l, h), 0.0), 5.0);
+        vec3 f0 = mix(vec3(0.04), base, m);
+        vec3 f = f0 + (1.0 - f0) * fh;
+        vec3 diffuse = ((1.0 - f) * base / PI + sheen * fh * base) * (1.0 - m);
+        vec3 specular = f * ggx(nh, r) * visibility(nl, nv, r);
+        float cr = clamp(clearcoat_roughness, 0.03, 1.0);
+        float fc = clearcoat * (0.04 + 0.96 * fh);
+        float coat = fc * ggx(nh, cr) * visibility(nl, nv, cr);
+        return ((diffuse + specular) * (1.0 - fc) + coat) * nl * PI;
+    }
+
+    // Fraction of the 3x3 shadow map texels around the fragment which see it from the key
+    // light, with a bias growing with the slope so surfaces don't shadow themselves
+    float shadow(vec3 l) {
+        vec3 p = (shadow_matrix * vec4(v_position, 1.0)).xyz * 0.5 + 0.5;
+        float bias = 0.001 + 0.004 * (1.0 - max(dot(normalize(v_normal), l), 0.0));
+        float lit = 0.0;
+        for (int x = -1; x <= 1; x++) {
+            for (int y = -1; y <= 1; y++) {
+                vec4 texel = texture(shadow_map, p.xy + vec2(x, y) * shadow_texel);
+                float depth = dot(texel, vec4(1.0, 1.0 / 255.0, 1.0 / 65025.0, 1.0 / 16581375.0));
+                lit += min(p.z, 1.0) - bias <= depth ? 1.0 : 0.0;
+            }
+        }
+        return lit / 9.0;
+    }
+
+    // Direction from the fragment to a light and the light's falloff there, the light is
+    // packed as described by `Light::uniform`
+    vec4 light_incidence(mat4 light) {
+        if (light[0].w == 0.0) {
+            return vec4(normalize(light[0].xyz), 1.0);
+        }
+        vec3 d = light[0].xyz - v_position;
+        float dist = length(d);
+        vec3 l = d / max(dist, 1e-6);
+        float x = dist / light[1].w;
+        float cone = smoothstep(light[2].w, mix(light[2].w, 1.0, 0.1), dot(-l, light[2].xyz));
+        return vec4(l, cone / (1.0 + x * x));
+    }
+
+    vec3 direct_light(mat4 light, vec3 n, vec3 v, vec3 kd, vec3 ks, float ns,
+                       float r, float m) {
+        if (light[1].rgb == vec3(0.0)) {
+            return vec3(0.0);
+        }
+        vec4 l = light_incidence(light);
+        vec3 brdf = physically_based ? shade_ggx(n, v, l.xyz, kd, r, m) : phong(n, v, l.xyz, kd, ks, ns);
+        // The key light is flagged in its last column when it casts shadows
+        float lit = light[3].x > 0.0 ? shadow(l.xyz) : 1.0;
+        return brdf * light[1].rgb * l.w * lit;
+    }
+
+    vec3 surface_normal() {
+        vec3 n = normalize(v_normal);
+        vec3 t = v_tangent.xyz - n * dot(n, v_tangent.xyz);
+        if (dot(t, t) < 1e-6) {
+            return n;
+        }
+        t = normalize(t);
+        vec3 b = cross(n, t) * v_tangent.w;
+        vec3 m = texture(normal_map, v_texcoord).xyz * 2.0 - 1.0;
+        return normalize(mat3(t, b, n) * m);
+    }
+
+    vec4 shade() {
+        vec4 p = vec4(v_position, 1.0);
+        if (dot(clip_plane0, p) < 0.0 || dot(clip_plane1, p) < 0.0 || dot(clip_plane2, p) < 0.0) {
+            discard;
+        }
+        if (texture(alpha_mask, v_texcoord).r < 0.5) {
+            discard;
+        }
+        vec3 n = surface_normal();
+        vec3 v = normalize(eye_position - v_position);
+        vec3 kd = v_color_diffuse * texture(diffuse_map, v_texcoord).rgb;
+        vec3 ks = v_color_specular.rgb * texture(specular_map, v_texcoord).rgb;
+        float ns = max(v_color_specular.a * texture(shininess_map, v_texcoord).r, 1.0);
+        // Materials without PBR values get a roughness matching their shininess
+        float r = pbr_material ? roughness * texture(roughness_map, v_texcoord).r
+            : pow(2.0 / (ns + 2.0), 0.25);
+        float m = pbr_material ? metallic * texture(metallic_map, v_texcoord).r : 0.0;
+        vec3 color = physically_based ? ggx_ambient(n, v, kd, r, m) : phong_ambient(n, v, kd, ks, ns);
+        color += direct_light(light0, n, v, kd, ks, ns, r, m);
+        color += direct_light(light1, n, v, kd, ks, ns, r, m);
+        color += direct_light(light2, n, v, kd, ks, ns, r, m);
+        color += direct_light(light3, n, v, kd, ks, ns, r, m);
+        color += emissive * texture(emissive_map, v_texcoord).rgb;
+        return vec4(color, opacity);
+    }
+";
+
+fn mesh_program<F: Facade>(facade: &F) -> Program {
+    let fragment_140 = format!(
+        "#version 140\n{}{}",
+        MESH_SHADING_140,
+        "
+            out vec4 f_color;
+
+            void main() {
+                f_color = shade();
+            }
+        "
+    );
+    program!(facade,
+        140 => {
+            vertex: MESH_VERTEX_140,
+            fragment: &fragment_140,
+        },
+
+        110 => {
+            vertex: "
+                #version 110
+
+                uniform mat4 persp_matrix;
+                uniform mat4 view_matrix;
+                uniform mat4 model_matrix;
+                uniform vec3 model_offset;
+
+                attribute vec3 position;
+                attribute vec3 normal;
+                attribute vec2 texcoord;
+                attribute vec4 tangent;
+                attribute vec3 color_diffuse;
+                attribute vec4 color_specular;
+                varying vec3 v_position;
+                varying vec3 v_normal;
+                varying vec2 v_texcoord;
+                varying vec4 v_tangent;
+                varying vec3 v_color_diffuse;
+                varying vec4 v_color_specular;
+
+                void main() {
+                    v_position = position + model_offset;
+                    v_normal = normal;
+                    v_texcoord = texcoord;
+                    v_tangent = tangent;
+                    v_color_diffuse = color_diffuse;
+                    v_color_specular = color_specular;
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(v_position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 110
+
+                uniform vec4 clip_plane0;
+                uniform vec4 clip_plane1;
+                uniform vec4 clip_plane2;
+                uniform float opacity;
+                uniform vec3 eye_position;
+                uniform mat4 light0;
+                uniform mat4 light1;
+                uniform mat4 light2;
+                uniform mat4 light3;
+                uniform sampler2D alpha_mask;
+                uniform sampler2D normal_map;
+                uniform sampler2D diffuse_map;
+                uniform sampler2D specular_map;
+                uniform sampler2D shininess_map;
+                uniform sampler2D emissive_map;
+                uniform vec3 emissive;
+                uniform bool physically_based;
+                uniform bool pbr_material;
+                uniform float roughness;
+                uniform float metallic;
+                uniform float sheen;
+                uniform float clearcoat;
+                uniform float clearcoat_roughness;
+                uniform sampler2D roughness_map;
+                uniform sampler2D metallic_map;
+                uniform sampler2D environment_irradiance;
+                uniform sampler2D environment_specular;
+                uniform float environment_levels;
+                uniform mat4 shadow_matrix;
+                uniform sampler2D shadow_map;
+                uniform float shadow_texel;
+
+                varying vec3 v_position;
+                varying vec3 v_normal;
+                varying vec2 v_texcoord;
+                varying vec4 v_tangent;
+                varying vec3 v_color_diffuse;
+                varying vec4 v_color_specular;
+
+                const float PI = 3.14159265;
+
+                vec2 equirect(vec3 d) {
+                    return vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+                }
+
+                vec3 irradiance(vec3 n) {
+                    return texture2D(environment_irradiance, equirect(n)).rgb;
+                }
+
+                // Environment light reflected by a surface of the given roughness. Fragment shaders
+                // here can only bias the automatically selected level, which is near the sharpest level
+                // for surfaces close enough to matter.
+                vec3 radiance(vec3 d, float r) {
+                    return texture2D(environment_specular, equirect(d), r * environment_levels).rgb;
+                }
+
+                // Analytic fit of the GGX reflectance integrated over the environment (Karis 2014)
+                vec3 environment_brdf(vec3 f0, float r, float nv) {
+                    vec4 c = r * vec4(-1.0, -0.0275, -0.572, 0.022) + vec4(1.0, 0.0425, 1.04, -0.04);
+                    float a = min(c.x * c.x, exp2(-9.28 * nv)) * c.x + c.y;
+                    return f0 * (a * -1.04 + c.z) + (a * 1.04 + c.w);
+                }
+
+                vec3 phong_ambient(vec3 n, vec3 v, vec3 kd, vec3 ks, float ns) {
+                    return kd * irradiance(n) + ks * radiance(reflect(-v, n), pow(2.0 / (ns + 2.0), 0.25));
+                }
+
+                vec3 phong(vec3 n, vec3 v, vec3 l, vec3 kd, vec3 ks, float ns) {
+                    float lum = max(dot(n, l), 0.0);
+                    float spec = lum > 0.0 ? pow(max(dot(n, normalize(l + v)), 0.0), ns) : 0.0;
+                    return kd * lum + ks * spec;
+                }
+
+                // GGX distribution with alpha = r * r
+                float ggx(float nh, float r) {
+                    float a2 = r * r * r * r;
+                    float d = nh * nh * (a2 - 1.0) + 1.0;
+                    return a2 / (PI * d * d);
+                }
+
+                // Smith-Schlick masking and shadowing divided by 4 (n.l) (n.v)
+                float visibility(float nl, float nv, float r) {
+                    float k = (r + 1.0) * (r + 1.0) / 8.0;
+                    return 0.25 / ((nl * (1.0 - k) + k) * (nv * (1.0 - k) + k));
+                }
+
+                vec3 ggx_ambient(vec3 n, vec3 v, vec3 base, float r, float m) {
+                    r = clamp(r, 0.03, 1.0);
+                    vec3 f0 = mix(vec3(0.04), base, m);
+                    return (1.0 - m) * base * irradiance(n)
+                        + radiance(reflect(-v, n), r) * environment_brdf(f0, r, max(dot(n, v), 1e-4));
+                }
+
+                // Metallic/roughness GGX with a clear coat lobe and Disney sheen, scaled by pi so a white
+                // diffuse surface is as bright as with the Phong shading
+                vec3 shade_ggx(vec3 n, vec3 v, vec3 l, vec3 base, float r, float m) {
+                    r = clamp(r, 0.03, 1.0);
+                    vec3 h = normalize(l + v);
+                    float nl = max(dot(n, l), 0.0);
+                    float nv = max(dot(n, v), 1e-4);
+                    float nh = max(dot(n, h), 0.0);
+                    float fh = pow(1.0 - max(dot(l, h), 0.0), 5.0);
+                    vec3 f0 = mix(vec3(0.04), base, m);
+                    vec3 f = f0 + (1.0 - f0) * fh;
+                    vec3 diffuse = ((1.0 - f) * base / PI + sheen * fh * base) * (1.0 - m);
+                    vec3 specular = f * ggx(nh, r) * visibility(nl, nv, r);
+                    float cr = clamp(clearcoat_roughness, 0.03, 1.0);
+                    float fc = clearcoat * (0.04 + 0.96 * fh);
+                    float coat = fc * ggx(nh, cr) * visibility(nl, nv, cr);
+                    return ((diffuse + specular) * (1.0 - fc) + coat) * nl * PI;
+                }
+
+                // Fraction of the 3x3 shadow map texels around the fragment which see it from the key
+                // light, with a bias growing with the slope so surfaces don't shadow themselves
+                float shadow(vec3 l) {
+                    vec3 p = (shadow_matrix * vec4(v_position, 1.0)).xyz * 0.5 + 0.5;
+                    float bias = 0.001 + 0.004 * (1.0 - max(dot(normalize(v_normal), l), 0.0));
+                    float lit = 0.0;
+                    for (int x = -1; x <= 1; x++) {
+                        for (int y = -1; y <= 1; y++) {
+                            vec4 texel = texture2D(shadow_map, p.xy + vec2(x, y) * shadow_texel);
+                            float depth = dot(texel, vec4(1.0, 1.0 / 255.0, 1.0 / 65025.0, 1.0 / 16581375.0));
+                            lit += min(p.z, 1.0) - bias <= depth ? 1.0 : 0.0;
+                        }
+                    }
+                    return lit / 9.0;
+                }
+
+                // Direction from the fragment to a light and the light's falloff there, the light is
+                // packed as described by `Light::uniform`
+                vec4 light_incidence(mat4 light) {
+                    if (light[0].w == 0.0) {
+                        return vec4(normalize(light[0].xyz), 1.0);
+                    }
+                    vec3 d = light[0].xyz - v_position;
+                    float dist = length(d);
+                    vec3 l = d / max(dist, 1e-6);
+                    float x = dist / light[1].w;
+                    float cone = smoothstep(light[2].w, mix(light[2].w, 1.0, 0.1), dot(-l, light[2].xyz));
+                    return vec4(l, cone / (1.0 + x * x));
+                }
+
+                vec3 direct_light(mat4 light, vec3 n, vec3 v, vec3 kd, vec3 ks, float ns,
+                                   float r, float m) {
+                    if (light[1].rgb == vec3(0.0)) {
+                        return vec3(0.0);
+                    }
+                    vec4 l = light_incidence(light);
+                    vec3 brdf = physically_based ? shade_ggx(n, v, l.xyz, kd, r, m) : phong(n, v, l.xyz, kd, ks, ns);
+                    // The key light is flagged in its last column when it casts shadows
+                    float lit = light[3].x > 0.0 ? shadow(l.xyz) : 1.0;
+                    return brdf * light[1].rgb * l.w * lit;
+                }
+
+                vec3 surface_normal() {
+                    vec3 n = normalize(v_normal);
+                    vec3 t = v_tangent.xyz - n * dot(n, v_tangent.xyz);
+                    if (dot(t, t) < 1e-6) {
+                        return n;
+                    }
+                    t = normalize(t);
+                    vec3 b = cross(n, t) * v_tangent.w;
+                    vec3 m = texture2D(normal_map, v_texcoord).xyz * 2.0 - 1.0;
+                    return normalize(mat3(t, b, n) * m);
+                }
+
+                void main() {
+                    vec4 p = vec4(v_position, 1.0);
+                    if (dot(clip_plane0, p) < 0.0 || dot(clip_plane1, p) < 0.0 || dot(clip_plane2, p) < 0.0) {
+                        discard;
+                    }
+                    if (texture2D(alpha_mask, v_texcoord).r < 0.5) {
+                        discard;
+                    }
+                    vec3 n = surface_normal();
+                    vec3 v = normalize(eye_position - v_position);
+                    vec3 kd = v_color_diffuse * texture2D(diffuse_map, v_texcoord).rgb;
+                    vec3 ks = v_color_specular.rgb * texture2D(specular_map, v_texcoord).rgb;
+                    float ns = max(v_color_specular.a * texture2D(shininess_map, v_texcoord).r, 1.0);
+                    // Materials without PBR values get a roughness matching their shininess
+                    float r = pbr_material ? roughness * texture2D(roughness_map, v_texcoord).r
+                        : pow(2.0 / (ns + 2.0), 0.25);
+                    float m = pbr_material ? metallic * texture2D(metallic_map, v_texcoord).r : 0.0;
+                    vec3 color = physically_based ? ggx_ambient(n, v, kd, r, m) : phong_ambient(n, v, kd, ks, ns);
+                    color += direct_light(light0, n, v, kd, ks, ns, r, m);
+                    color += direct_light(light1, n, v, kd, ks, ns, r, m);
+                    color += direct_light(light2, n, v, kd, ks, ns, r, m);
+                    color += direct_light(light3, n, v, kd, ks, ns, r, m);
+                    color += emissive * texture2D(emissive_map, v_texcoord).rgb;
+                    gl_FragColor = vec4(color, opacity);
+                }
+            ",
+        },
+
+        100 => {
+            vertex: "
+                #version 100
+
+                uniform lowp mat4 persp_matrix;
+                uniform lowp mat4 view_matrix;
+                uniform lowp mat4 model_matrix;
+                uniform highp vec3 model_offset;
+
+                attribute lowp vec3 position;
+                attribute lowp vec3 normal;
+                attribute mediump vec2 texcoord;
+                attribute mediump vec4 tangent;
+                attribute lowp vec3 color_diffuse;
+                attribute mediump vec4 color_specular;
+                varying highp vec3 v_position;
+                varying lowp vec3 v_normal;
+                varying mediump vec2 v_texcoord;
+                varying mediump vec4 v_tangent;
+                varying lowp vec3 v_color_diffuse;
+                varying mediump vec4 v_color_specular;
+
+                void main() {
+                    v_position = position + model_offset;
+                    v_normal = normal;
+                    v_texcoord = texcoord;
+                    v_tangent = tangent;
+                    v_color_diffuse = color_diffuse;
+                    v_color_specular = color_specular;
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(v_position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 100
+
+                uniform highp vec4 clip_plane0;
+                uniform highp vec4 clip_plane1;
+                uniform highp vec4 clip_plane2;
+                uniform lowp float opacity;
+                uniform highp vec3 eye_position;
+                uniform highp mat4 light0;
+                uniform highp mat4 light1;
+                uniform highp mat4 light2;
+                uniform highp mat4 light3;
+                uniform lowp sampler2D alpha_mask;
+                uniform lowp sampler2D normal_map;
+                uniform lowp sampler2D diffuse_map;
+                uniform lowp sampler2D specular_map;
+                uniform lowp sampler2D shininess_map;
+                uniform lowp sampler2D emissive_map;
+                uniform lowp vec3 emissive;
+                uniform bool physically_based;
+                uniform bool pbr_material;
+                uniform lowp float roughness;
+                uniform lowp float metallic;
+                uniform lowp float sheen;
+                uniform lowp float clearcoat;
+                uniform lowp float clearcoat_roughness;
+                uniform lowp sampler2D roughness_map;
+                uniform lowp sampler2D metallic_map;
+                uniform lowp sampler2D environment_irradiance;
+                uniform lowp sampler2D environment_specular;
+                uniform mediump float environment_levels;
+                uniform highp mat4 shadow_matrix;
+                uniform highp sampler2D shadow_map;
+                uniform highp float shadow_texel;
+
+                varying highp vec3 v_position;
+                varying lowp vec3 v_normal;
+                varying mediump vec2 v_texcoord;
+                varying mediump vec4 v_tangent;
+                varying lowp vec3 v_color_diffuse;
+                varying mediump vec4 v_color_specular;
+
+                const mediump float PI = 3.14159265;
+
+                mediump vec2 equirect(mediump vec3 d) {
+                    return vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+                }
+
+                mediump vec3 irradiance(mediump vec3 n) {
+                    return texture2D(environment_irradiance, equirect(n)).rgb;
+                }
+
+                // Environment light reflected by a surface of the given roughness. Fragment shaders
+                // here can only bias the automatically selected level, which is near the sharpest level
+                // for surfaces close enough to matter.
+                mediump vec3 radiance(mediump vec3 d, mediump float r) {
+                    return texture2D(environment_specular, equirect(d), r * environment_levels).rgb;
+                }
+
+                // Analytic fit of the GGX reflectance integrated over the environment (Karis 2014)
+                mediump vec3 environment_brdf(mediump vec3 f0, mediump float r, mediump float nv) {
+                    mediump vec4 c = r * vec4(-1.0, -0.0275, -0.572, 0.022) + vec4(1.0, 0.0425, 1.04, -0.04);
+                    mediump float a = min(c.x * c.x, exp2(-9.28 * nv)) * c.x + c.y;
+                    return f0 * (a * -1.04 + c.z) + (a * 1.04 + c.w);
+                }
+
+                mediump vec3 phong_ambient(mediump vec3 n, mediump vec3 v, mediump vec3 kd, mediump vec3 ks, mediump float ns) {
+                    return kd * irradiance(n) + ks * radiance(reflect(-v, n), pow(2.0 / (ns + 2.0), 0.25));
+                }
+
+                mediump vec3 phong(mediump vec3 n, mediump vec3 v, mediump vec3 l, mediump vec3 kd, mediump vec3 ks, mediump float ns) {
+                    mediump float lum = max(dot(n, l), 0.0);
+                    mediump float spec = lum > 0.0 ? pow(max(dot(n, normalize(l + v)), 0.0), ns) : 0.0;
+                    return kd * lum + ks * spec;
+                }
+
+                // GGX distribution with alpha = r * r
+                mediump float ggx(mediump float nh, mediump float r) {
+                    mediump float a2 = r * r * r * r;
+                    mediump float d = nh * nh * (a2 - 1.0) + 1.0;
+                    return a2 / (PI * d * d);
+                }
+
+                // Smith-Schlick masking and shadowing divided by 4 (n.l) (n.v)
+                mediump float visibility(mediump float nl, mediump float nv, mediump float r) {
+                    mediump float k = (r + 1.0) * (r + 1.0) / 8.0;
+                    return 0.25 / ((nl * (1.0 - k) + k) * (nv * (1.0 - k) + k));
+                }
+
+                mediump vec3 ggx_ambient(mediump vec3 n, mediump vec3 v, mediump vec3 base, mediump float r, mediump float m) {
+                    // Lower roughness underflows r^4 at medium precision
+                    r = clamp(r, 0.09, 1.0);
+                    mediump vec3 f0 = mix(vec3(0.04), base, m);
+                    return (1.0 - m) * base * irradiance(n)
+                        + radiance(reflect(-v, n), r) * environment_brdf(f0, r, max(dot(n, v), 1e-4));
+                }
+
+                // Metallic/roughness GGX with a clear coat lobe and Disney sheen, scaled by pi so a white
+                // diffuse surface is as bright as with the Phong shading
+                mediump vec3 shade_ggx(mediump vec3 n, mediump vec3 v, mediump vec3 l, mediump vec3 base, mediump float r, mediump float m) {
+                    // Lower roughness underflows r^4 at medium precision
+                    r = clamp(r, 0.09, 1.0);
+                    mediump vec3 h = normalize(l + v);
+                    mediump float nl = max(dot(n, l), 0.0);
+                    mediump float nv = max(dot(n, v), 1e-4);
+                    mediump float nh = max(dot(n, h), 0.0);
+                    mediump float fh = pow(1.0 - max(dot(l, h), 0.0), 5.0);
+                    mediump vec3 f0 = mix(vec3(0.04), base, m);
+                    mediump vec3 f = f0 + (1.0 - f0) * fh;
+                    mediump vec3 diffuse = ((1.0 - f) * base / PI + sheen * fh * base) * (1.0 - m);
+                    mediump vec3 specular = f * ggx(nh, r) * visibility(nl, nv, r);
+                    mediump float cr = clamp(clearcoat_roughness, 0.09, 1.0);
+                    mediump float fc = clearcoat * (0.04 + 0.96 * fh);
+                    mediump float coat = fc * ggx(nh, cr) * visibility(nl, nv, cr);
+                    return ((diffuse + specular) * (1.0 - fc) + coat) * nl * PI;
+                }
+
+                // Fraction of the 3x3 shadow map texels around the fragment which see it from the key
+                // light, with a bias growing with the slope so surfaces don't shadow themselves
+                mediump float shadow(highp vec3 l) {
+                    highp vec3 p = (shadow_matrix * vec4(v_position, 1.0)).xyz * 0.5 + 0.5;
+                    highp float bias = 0.001 + 0.004 * (1.0 - max(dot(normalize(v_normal), l), 0.0));
+                    mediump float lit = 0.0;
+                    for (int x = -1; x <= 1; x++) {
+                        for (int y = -1; y <= 1; y++) {
+                            highp vec4 texel = texture2D(shadow_map, p.xy + vec2(x, y) * shadow_texel);
+                            highp float depth = dot(texel, vec4(1.0, 1.0 / 255.0, 1.0 / 65025.0, 1.0 / 16581375.0));
+                            lit += min(p.z, 1.0) - bias <= depth ? 1.0 : 0.0;
+                        }
+                    }
+                    return lit / 9.0;
+                }
+
+                // Direction from the fragment to a light and the light's falloff there, the light is
+                // packed as described by `Light::uniform`
+                highp vec4 light_incidence(highp mat4 light) {
+                    if (light[0].w == 0.0) {
+                        return vec4(normalize(light[0].xyz), 1.0);
+                    }
+                    highp vec3 d = light[0].xyz - v_position;
+                    highp float dist = length(d);
+                    highp vec3 l = d / max(dist, 1e-6);
+                    highp float x = dist / light[1].w;
+                    highp float cone = smoothstep(light[2].w, mix(light[2].w, 1.0, 0.1), dot(-l, light[2].xyz));
+                    return vec4(l, cone / (1.0 + x * x));
+                }
+
+                mediump vec3 direct_light(highp mat4 light, mediump vec3 n, mediump vec3 v, mediump vec3 kd, mediump vec3 ks, mediump float ns,
+                                   mediump float r, mediump float m) {
+                    if (light[1].rgb == vec3(0.0)) {
+                        return vec3(0.0);
+                    }
+                    highp vec4 l = light_incidence(light);
+                    mediump vec3 brdf = physically_based ? shade_ggx(n, v, l.xyz, kd, r, m) : phong(n, v, l.xyz, kd, ks, ns);
+                    // The key light is flagged in its last column when it casts shadows
+                    mediump float lit = light[3].x > 0.0 ? shadow(l.xyz) : 1.0;
+                    return brdf * light[1].rgb * l.w * lit;
+                }
+
+                mediump vec3 surface_normal() {
+                    mediump vec3 n = normalize(v_normal);
+                    mediump vec3 t = v_tangent.xyz - n * dot(n, v_tangent.xyz);
+                    if (dot(t, t) < 1e-6) {
+                        return n;
+                    }
+                    t = normalize(t);
+                    mediump vec3 b = cross(n, t) * v_tangent.w;
+                    mediump vec3 m = texture2D(normal_map, v_texcoord).xyz * 2.0 - 1.0;
+                    return normalize(mat3(t, b, n) * m);
+                }
+
+                void main() {
+                    highp vec4 p = vec4(v_position, 1.0);
+                    if (dot(clip_plane0, p) < 0.0 || dot(clip_plane1, p) < 0.0 || dot(clip_plane2, p) < 0.0) {
+                        discard;
+                    }
+                    if (texture2D(alpha_mask, v_texcoord).r < 0.5) {
+                        discard;
+                    }
+                    mediump vec3 n = surface_normal();
+                    mediump vec3 v = normalize(eye_position - v_position);
+                    lowp vec3 kd = v_color_diffuse * texture2D(diffuse_map, v_texcoord).rgb;
+                    lowp vec3 ks = v_color_specular.rgb * texture2D(specular_map, v_texcoord).rgb;
+                    mediump float ns = max(v_color_specular.a * texture2D(shininess_map, v_texcoord).r, 1.0);
+                    // Materials without PBR values get a roughness matching their shininess
+                    mediump float r = pbr_material ? roughness * texture2D(roughness_map, v_texcoord).r
+                        : pow(2.0 / (ns + 2.0), 0.25);
+                    mediump float m = pbr_material ? metallic * texture2D(metallic_map, v_texcoord).r : 0.0;
+                    mediump vec3 color = physically_based ? ggx_ambient(n, v, kd, r, m) : phong_ambient(n, v, kd, ks, ns);
+                    color += direct_light(light0, n, v, kd, ks, ns, r, m);
+                    color += direct_light(light1, n, v, kd, ks, ns, r, m);
+                    color += direct_light(light2, n, v, kd, ks, ns, r, m);
+                    color += direct_light(light3, n, v, kd, ks, ns, r, m);
+                    color += emissive * texture2D(emissive_map, v_texcoord).rgb;
+                    gl_FragColor = vec4(color, opacity);
+                }
+            ",
+        },
+    )
+    .unwrap()
+}
+
+/// Solid color program for the clip plane caps, clipped by the other planes the same
+/// way as the mesh
+fn cap_program<F: Facade>(facade: &F) -> Program {
+    program!(facade,
+        140 => {
+            vertex: "
+                #version 140
+
+                uniform mat4 persp_matrix;
+                uniform mat4 view_matrix;
+                uniform mat4 model_matrix;
+
+                in vec3 position;
+                in vec3 color;
+                out vec3 v_position;
+                out vec3 v_color;
+
+                void main() {
+                    v_position = position;
+                    v_color = color;
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 140
+
+                uniform vec4 clip_plane0;
+                uniform vec4 clip_plane1;
+                uniform vec4 clip_plane2;
+
+                in vec3 v_position;
+                in vec3 v_color;
+                out vec4 f_color;
+
+                void main() {
+                    vec4 p = vec4(v_position, 1.0);
+                    if (dot(clip_plane0, p) < 0.0 || dot(clip_plane1, p) < 0.0 || dot(clip_plane2, p) < 0.0) {
+                        discard;
+                    }
+                    f_color = vec4(v_color, 1.0);
+                }
+            ",
+        },
+
+        110 => {
+            vertex: "
+                #version 110
+
+                uniform mat4 persp_matrix;
+                uniform mat4 view_matrix;
+                uniform mat4 model_matrix;
+
+                attribute vec3 position;
+                attribute vec3 color;
+                varying vec3 v_position;
+                varying vec3 v_color;
+
+                void main() {
+                    v_position = position;
+                    v_color = color;
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 110
+
+                uniform vec4 clip_plane0;
+                uniform vec4 clip_plane1;
+                uniform vec4 clip_plane2;
+
+                varying vec3 v_position;
+                varying vec3 v_color;
+
+                void main() {
+                    vec4 p = vec4(v_position, 1.0);
+                    if (dot(clip_plane0, p) < 0.0 || dot(clip_plane1, p) < 0.0 || dot(clip_plane2, p) < 0.0) {
+                        discard;
+                    }
+                    gl_FragColor = vec4(v_color, 1.0);
+                }
+            ",
+        },
+
+        100 => {
+            vertex: "
+                #version 100
+
+                uniform lowp mat4 persp_matrix;
+                uniform lowp mat4 view_matrix;
+                uniform lowp mat4 model_matrix;
+
+                attribute lowp vec3 position;
+                attribute lowp vec3 color;
+                varying highp vec3 v_position;
+                varying lowp vec3 v_color;
+
+                void main() {
+                    v_position = position;
+                    v_color = color;
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 100
+
+                uniform highp vec4 clip_plane0;
+                uniform highp vec4 clip_plane1;
+                uniform highp vec4 clip_plane2;
+
+                varying highp vec3 v_position;
+                varying lowp vec3 v_color;
+
+                void main() {
+                    highp vec4 p = vec4(v_position, 1.0);
+                    if (dot(clip_plane0, p) < 0.0 || dot(clip_plane1, p) < 0.0 || dot(clip_plane2, p) < 0.0) {
+                        discard;
+                    }
+                    gl_FragColor = vec4(v_color, 1.0);
+                }
+            ",
+        },
+    )
+    .unwrap()
+}
@@ -0,0 +1,502 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use cgmath::{self, InnerSpace, Matrix4, Point3, Vector3};
+use glium::backend::{Context, Facade};
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+use glium::texture::{DepthFormat, MipmapsOption, UncompressedFloatFormat};
+use glium::uniforms::{
+    MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerBehavior, SamplerWrapFunction,
+};
+use glium::{self, Program, Surface, Texture2d, VertexBuffer};
+
+use support::bounds::Bounds;
+use support::overlay::LineVertex;
+use support::Scene;
+
+/// Width and height of the shadow map in texels
+const SHADOW_MAP_SIZE: u32 = 2048;
+/// Color of the ground plane catching the shadows when it's fully lit
+const GROUND_COLOR: [f32; 3] = [0.35, 0.35, 0.35];
+
+/// What the shadow map was last rendered with
+#[derive(PartialEq)]
+struct RenderedState {
+    light_matrix: [[f32; 4]; 4],
+    clip_planes: [[f32; 4]; 3],
+    /// The visibility and exploded view offset of each mesh
+    meshes: Vec<(bool, [f32; 3])>,
+}
+
+/// Shadows cast by the key light. The depth seen from the light is packed into the
+/// channels of an 8 bit color texture, which every GLSL version can render to and sample.
+pub struct ShadowMap {
+    context: Rc<Context>,
+    depth_program: Program,
+    ground_program: Program,
+    /// Packed depth of the nearest surface to the light
+    texture: Texture2d,
+    depth_buffer: DepthRenderBuffer,
+    /// Plane at the bottom of the model which catches its shadow
+    ground: VertexBuffer<LineVertex>,
+    /// Set once the map is rendered, so it's only rendered again when it would change
+    rendered: RefCell<Option<RenderedState>>,
+}
+
+impl ShadowMap {
+    /// Returns None if the context can't render to the shadow map
+    pub fn new<F: Facade>(facade: &F, scene: &Scene) -> Option<ShadowMap> {
+        let texture = Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::U8U8U8U8,
+            MipmapsOption::NoMipmap,
+            SHADOW_MAP_SIZE,
+            SHADOW_MAP_SIZE,
+        )
+        .ok()?;
+        let depth_buffer =
+            DepthRenderBuffer::new(facade, DepthFormat::I24, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE)
+                .ok()?;
+        Some(ShadowMap {
+            context: facade.get_context().clone(),
+            depth_program: depth_program(facade),
+            ground_program: ground_program(facade),
+            texture,
+            depth_buffer,
+            ground: VertexBuffer::new(facade, &ground_quad(&scene.bounds)).unwrap(),
+            rendered: RefCell::new(None),
+        })
+    }
+
+    /// Rebuild the ground plane for a newly loaded scene
+    pub fn set_scene<F: Facade>(&mut self, facade: &F, scene: &Scene) {
+        self.ground = VertexBuffer::new(facade, &ground_quad(&scene.bounds)).unwrap();
+        *self.rendered.get_mut() = None;
+    }
+
+    /// Returns the sampler reading the packed depths without filtering them
+    pub fn sampler(&self) -> Sampler<'_, Texture2d> {
+        Sampler(
+            &self.texture,
+            SamplerBehavior {
+                wrap_function: (
+                    SamplerWrapFunction::Clamp,
+                    SamplerWrapFunction::Clamp,
+                    SamplerWrapFunction::Clamp,
+                ),
+                minify_filter: MinifySamplerFilter::Nearest,
+                magnify_filter: MagnifySamplerFilter::Nearest,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn texel_size(&self) -> f32 {
+        1.0 / SHADOW_MAP_SIZE as f32
+    }
+
+    /// Render the depth of the opaque visible meshes as seen from the light through
+    /// `light_matrix`, with the clip planes applied to the meshes. Nothing is rendered if
+    /// the light, clip planes and meshes are the same as the last time.
+    pub fn render(
+        &self,
+        scene: &Scene,
+        light_matrix: [[f32; 4]; 4],
+        clip_planes: &[[f32; 4]; 3],
+        white: &Texture2d,
+    ) {
+        let state = RenderedState {
+            light_matrix,
+            clip_planes: *clip_planes,
+            meshes: scene.meshes.iter().map(|m| (m.visible, m.offset)).collect(),
+        };
+        if self.rendered.borrow().as_ref() == Some(&state) {
+            return;
+        }
+        let mut framebuffer =
+            SimpleFrameBuffer::with_depth_buffer(&self.context, &self.texture, &self.depth_buffer)
+                .unwrap();
+        // Packs to a depth just past the far plane
+        framebuffer.clear_color_and_depth((1.0, 1.0, 1.0, 1.0), 1.0);
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let triangles = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        for mesh in scene.visible_meshes().filter(|m| !m.is_transparent()) {
+            let uniforms = uniform! {
+                light_matrix: light_matrix,
+                model_offset: mesh.offset,
+                alpha_mask: mesh.material.alpha_mask.as_deref().unwrap_or(white),
+                clip_plane0: clip_planes[0],
+                clip_plane1: clip_planes[1],
+                clip_plane2: clip_planes[2],
+            };
+            framebuffer
+                .draw(
+                    &mesh.vertex_buffer,
+                    triangles,
+                    &self.depth_program,
+                    &uniforms,
+                    &params,
+                )
+                .unwrap();
+        }
+        *self.rendered.borrow_mut() = Some(state);
+    }
+
+    /// Draw the ground plane under the model, darkened where the model's shadow falls
+    pub fn draw_ground<S: Surface>(
+        &self,
+        target: &mut S,
+        matrices: [[[f32; 4]; 4]; 3],
+        light_matrix: [[f32; 4]; 4],
+        params: &glium::DrawParameters,
+    ) {
+        let uniforms = uniform! {
+            persp_matrix: matrices[0],
+            view_matrix: matrices[1],
+            model_matrix: matrices[2],
+            shadow_matrix: light_matrix,
+            shadow_map: self.sampler(),
+            shadow_texel: self.texel_size(),
+            ground_color: GROUND_COLOR,
+        };
+        target
+            .draw(
+                &self.ground,
+                glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
+                &self.ground_program,
+                &uniforms,
+                params,
+            )
+            .unwrap();
+    }
+}
+
+/// Returns the orthographic projection from model space to the shadow map of a directional
+/// light shining from `to_light`, fitted tightly around the corners of the bounds
+pub fn light_matrix(to_light: [f32; 3], bounds: &Bounds) -> [[f32; 4]; 4] {
+    let to_light = Vector3::from(to_light).normalize();
+    let center = Point3::from(bounds.center());
+    let up = if to_light.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let view = Matrix4::look_at(center + to_light, center, up);
+    let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+    for c in &bounds.corners() {
+        let p = (view * Point3::from(*c).to_homogeneous()).truncate();
+        min = Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+    // Pad the box slightly so surfaces on the bounds aren't clipped by it
+    let pad = 0.01 * (max - min).magnitude().max(1e-6);
+    // The light looks down -z, so the nearest corner has the largest z
+    let projection = cgmath::ortho(
+        min.x - pad,
+        max.x + pad,
+        min.y - pad,
+        max.y + pad,
+        -max.z - pad,
+        -min.z + pad,
+    );
+    (projection * view).into()
+}
+
+/// A square on the XZ plane just below the bottom of the bounds, covering the ground grid
+fn ground_quad(bounds: &Bounds) -> Vec<LineVertex> {
+    if bounds.is_empty() {
+        return Vec::new();
+    }
+    let center = bounds.center();
+    let half = 0.75 * bounds.diagonal();
+    // Sit just under the grid lines so they aren't hidden by the plane
+    let y = bounds.min[1] - 1e-3 * bounds.diagonal();
+    let corner = |x: f32, z: f32| LineVertex {
+        position: [center[0] + x * half, y, center[2] + z * half],
+        color: GROUND_COLOR,
+    };
+    vec![
+        corner(-1.0, -1.0),
+        corner(-1.0, 1.0),
+        corner(1.0, -1.0),
+        corner(1.0, 1.0),
+    ]
+}
+
+fn depth_program<F: Facade>(facade: &F) -> Program {
+    program!(facade,
+        140 => {
+            vertex: "
+                #version 140
+
+                uniform mat4 light_matrix;
+                uniform vec3 model_offset;
+
+                in vec3 position;
+                in vec2 texcoord;
+                out vec3 v_position;
+                out vec2 v_texcoord;
+
+                void main() {
+                    v_position = position + model_offset;
+                    v_texcoord = texcoord;
+                    gl_Position = light_matrix * vec4(v_position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 140
+
+                uniform vec4 clip_plane0;
+                uniform vec4 clip_plane1;
+                uniform vec4 clip_plane2;
+                uniform sampler2D alpha_mask;
+
+                in vec3 v_position;
+                in vec2 v_texcoord;
+                out vec4 f_color;
+
+                void main() {
+                    vec4 p = vec4(v_position, 1.0);
+                    if (dot(clip_plane0, p) < 0.0 || dot(clip_plane1, p) < 0.0 || dot(clip_plane2, p) < 0.0) {
+                        discard;
+                    }
+                    if (texture(alpha_mask, v_texcoord).r < 0.5) {
+                        discard;
+                    }
+                    vec4 depth = fract(gl_FragCoord.z * vec4(1.0, 255.0, 65025.0, 16581375.0));
+                    f_color = depth - depth.yzww * vec4(1.0 / 255.0, 1.0 / 255.0, 1.0 / 255.0, 0.0);
+                }
+            ",
+        },
+
+        110 => {
+            vertex: "
+                #version 110
+
+                uniform mat4 light_matrix;
+                uniform vec3 model_offset;
+
+                attribute vec3 position;
+                attribute vec2 texcoord;
+                varying vec3 v_position;
+                varying vec2 v_texcoord;
+
+                void main() {
+                    v_position = position + model_offset;
+                    v_texcoord = texcoord;
+                    gl_Position = light_matrix * vec4(v_position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 110
+
+                uniform vec4 clip_plane0;
+                uniform vec4 clip_plane1;
+                uniform vec4 clip_plane2;
+                uniform sampler2D alpha_mask;
+
+                varying vec3 v_position;
+                varying vec2 v_texcoord;
+
+                void main() {
+                    vec4 p = vec4(v_position, 1.0);
+                    if (dot(clip_plane0, p) < 0.0 || dot(clip_plane1, p) < 0.0 || dot(clip_plane2, p) < 0.0) {
+                        discard;
+                    }
+                    if (texture2D(alpha_mask, v_texcoord).r < 0.5) {
+                        discard;
+                    }
+                    vec4 depth = fract(gl_FragCoord.z * vec4(1.0, 255.0, 65025.0, 16581375.0));
+                    gl_FragColor = depth - depth.yzww * vec4(1.0 / 255.0, 1.0 / 255.0, 1.0 / 255.0, 0.0);
+                }
+            ",
+        },
+
+        100 => {
+            vertex: "
+                #version 100
+
+                uniform highp mat4 light_matrix;
+                uniform highp vec3 model_offset;
+
+                attribute highp vec3 position;
+                attribute mediump vec2 texcoord;
+                varying highp vec3 v_position;
+                varying mediump vec2 v_texcoord;
+
+                void main() {
+                    v_position = position + model_offset;
+                    v_texcoord = texcoord;
+                    gl_Position = light_matrix * vec4(v_position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 100
+
+                uniform highp vec4 clip_plane0;
+                uniform highp vec4 clip_plane1;
+                uniform highp vec4 clip_plane2;
+                uniform lowp sampler2D alpha_mask;
+
+                varying highp vec3 v_position;
+                varying mediump vec2 v_texcoord;
+
+                void main() {
+                    highp vec4 p = vec4(v_position, 1.0);
+                    if (dot(clip_plane0, p) < 0.0 || dot(clip_plane1, p) < 0.0 || dot(clip_plane2, p) < 0.0) {
+                        discard;
+                    }
+                    if (texture2D(alpha_mask, v_texcoord).r < 0.5) {
+                        discard;
+                    }
+                    highp vec4 depth = fract(gl_FragCoord.z * vec4(1.0, 255.0, 65025.0, 16581375.0));
+                    gl_FragColor = depth - depth.yzww * vec4(1.0 / 255.0, 1.0 / 255.0, 1.0 / 255.0, 0.0);
+                }
+            ",
+        },
+    )
+    .unwrap()
+}
+
+fn ground_program<F: Facade>(facade: &F) -> Program {
+    program!(facade,
+        140 => {
+            vertex: "
+                #version 140
+
+                uniform mat4 persp_matrix;
+                uniform mat4 view_matrix;
+                uniform mat4 model_matrix;
+                uniform mat4 shadow_matrix;
+
+                in vec3 position;
+                out vec4 v_shadow;
+
+                void main() {
+                    v_shadow = shadow_matrix * vec4(position, 1.0);
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 140
+
+                uniform sampler2D shadow_map;
+                uniform float shadow_texel;
+                uniform vec3 ground_color;
+
+                in vec4 v_shadow;
+                out vec4 f_color;
+
+                void main() {
+                    vec3 p = v_shadow.xyz / v_shadow.w * 0.5 + 0.5;
+                    float lit = 0.0;
+                    for (int x = -1; x <= 1; x++) {
+                        for (int y = -1; y <= 1; y++) {
+                            vec4 texel = texture(shadow_map, p.xy + vec2(x, y) * shadow_texel);
+                            float depth = dot(texel, vec4(1.0, 1.0 / 255.0, 1.0 / 65025.0, 1.0 / 16581375.0));
+                            lit += min(p.z, 1.0) - 0.002 <= depth ? 1.0 : 0.0;
+                        }
+                    }
+                    f_color = vec4(ground_color * (0.4 + 0.6 * lit / 9.0), 1.0);
+                }
+            ",
+        },
+
+        110 => {
+            vertex: "
+                #version 110
+
+                uniform mat4 persp_matrix;
+                uniform mat4 view_matrix;
+                uniform mat4 model_matrix;
+                uniform mat4 shadow_matrix;
+
+                attribute vec3 position;
+                varying vec4 v_shadow;
+
+                void main() {
+                    v_shadow = shadow_matrix * vec4(position, 1.0);
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 110
+
+                uniform sampler2D shadow_map;
+                uniform float shadow_texel;
+                uniform vec3 ground_color;
+
+                varying vec4 v_shadow;
+
+                void main() {
+                    vec3 p = v_shadow.xyz / v_shadow.w * 0.5 + 0.5;
+                    float lit = 0.0;
+                    for (int x = -1; x <= 1; x++) {
+                        for (int y = -1; y <= 1; y++) {
+                            vec4 texel = texture2D(shadow_map, p.xy + vec2(x, y) * shadow_texel);
+                            float depth = dot(texel, vec4(1.0, 1.0 / 255.0, 1.0 / 65025.0, 1.0 / 16581375.0));
+                            lit += min(p.z, 1.0) - 0.002 <= depth ? 1.0 : 0.0;
+                        }
+                    }
+                    gl_FragColor = vec4(ground_color * (0.4 + 0.6 * lit / 9.0), 1.0);
+                }
+            ",
+        },
+
+        100 => {
+            vertex: "
+                #version 100
+
+                uniform highp mat4 persp_matrix;
+                uniform highp mat4 view_matrix;
+                uniform highp mat4 model_matrix;
+                uniform highp mat4 shadow_matrix;
+
+                attribute highp vec3 position;
+                varying highp vec4 v_shadow;
+
+                void main() {
+                    v_shadow = shadow_matrix * vec4(position, 1.0);
+                    gl_Position = persp_matrix * view_matrix * model_matrix * vec4(position, 1.0);
+                }
+            ",
+
+            fragment: "
+                #version 100
+
+                uniform highp sampler2D shadow_map;
+                uniform highp float shadow_texel;
+                uniform lowp vec3 ground_color;
+
+                varying highp vec4 v_shadow;
+
+                void main() {
+                    highp vec3 p = v_shadow.xyz / v_shadow.w * 0.5 + 0.5;
+                    mediump float lit = 0.0;
+                    for (int x = -1; x <= 1; x++) {
+                        for (int y = -1; y <= 1; y++) {
+                            highp vec4 texel = texture2D(shadow_map, p.xy + vec2(x, y) * shadow_texel);
+                            highp float depth = dot(texel, vec4(1.0, 1.0 / 255.0, 1.0 / 65025.0, 1.0 / 16581375.0));
+                            lit += min(p.z, 1.0) - 0.002 <= depth ? 1.0 : 0.0;
+                        }
+                    }
+                    gl_FragColor = vec4(ground_color * (0.4 + 0.6 * lit / 9.0), 1.0);
+                }
+            ",
+        },
+    )
+    .unwrap()
+}
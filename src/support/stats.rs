@@ -0,0 +1,154 @@
+use cgmath::{InnerSpace, Vector3};
+use std::time::Duration;
+
+use support::bounds::Bounds;
+use support::measure::{self, Measurements};
+use support::Scene;
+
+/// Time spent in each stage of loading a file
+#[derive(Copy, Clone, Debug)]
+pub struct LoadTimes {
+    /// Parsing the OBJ file and its material libraries
+    pub parse: Duration,
+    /// Building the vertex data from the parsed models
+    pub process: Duration,
+    /// Uploading the vertex data to the GPU
+    pub upload: Duration,
+}
+
+impl LoadTimes {
+    pub fn total(&self) -> Duration {
+        self.parse + self.process + self.upload
+    }
+}
+
+/// Returns the duration in milliseconds
+pub fn duration_ms(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + f64::from(d.subsec_nanos()) * 1e-6
+}
+
+/// Returns the area of the triangle
+pub fn triangle_area(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> f32 {
+    let a = Vector3::from(a);
+    let e1 = Vector3::from(b) - a;
+    let e2 = Vector3::from(c) - a;
+    0.5 * e1.cross(e2).magnitude()
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.2} MiB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.2} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+fn format_bounds(b: &Bounds) -> String {
+    format!(
+        "[{:.3}, {:.3}, {:.3}] to [{:.3}, {:.3}, {:.3}]",
+        b.min[0], b.min[1], b.min[2], b.max[0], b.max[1], b.max[2]
+    )
+}
+
+fn yes_no(b: bool) -> &'static str {
+    if b {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn print_measurements(m: &Measurements) {
+    let volume = match m.closed_volume() {
+        Some(v) => format!("{:.4}", v),
+        None => "n/a (not closed)".to_owned(),
+    };
+    println!(
+        "    Surface area: {:.4}, volume: {}, centroid: [{:.3}, {:.3}, {:.3}]",
+        m.surface_area, volume, m.centroid[0], m.centroid[1], m.centroid[2]
+    );
+}
+
+/// Print the per-model and total statistics of the scene, positions and areas are
+/// in the model's own units
+pub fn print_report(scene: &Scene) {
+    println!("Mesh statistics:");
+    for m in &scene.models {
+        println!("  Model {}", m.name);
+        println!(
+            "    {} vertices, {} indices, {} triangles",
+            m.num_vertices, m.num_indices, m.num_triangles
+        );
+        println!(
+            "    Material: {}, normals: {}, UVs: {}",
+            m.material.as_deref().unwrap_or("default"),
+            yes_no(m.has_normals),
+            yes_no(m.has_texcoords)
+        );
+        println!("    Bounds: {}", format_bounds(&m.bounds));
+        print_measurements(&m.measurements);
+        println!(
+            "    {} boundary edges, {} non-manifold edges, {} holes",
+            m.num_boundary_edges, m.num_non_manifold_edges, m.num_holes
+        );
+    }
+    let num_vertices: usize = scene.models.iter().map(|m| m.num_vertices).sum();
+    let num_indices: usize = scene.models.iter().map(|m| m.num_indices).sum();
+    let measurements: Vec<_> = scene.models.iter().map(|m| m.measurements).collect();
+    println!(
+        "  Total: {} models, {} materials, {} vertices, {} indices, {} triangles",
+        scene.models.len(),
+        scene.num_materials,
+        num_vertices,
+        num_indices,
+        scene.num_triangles()
+    );
+    println!("    Bounds: {}", format_bounds(&scene.bounds));
+    print_measurements(&measure::total(&measurements));
+    println!(
+        "    {} boundary edges, {} non-manifold edges, {} holes",
+        scene
+            .models
+            .iter()
+            .map(|m| m.num_boundary_edges)
+            .sum::<usize>(),
+        scene
+            .models
+            .iter()
+            .map(|m| m.num_non_manifold_edges)
+            .sum::<usize>(),
+        scene.models.iter().map(|m| m.num_holes).sum::<usize>()
+    );
+    println!(
+        "    Estimated GPU memory: {}",
+        format_bytes(scene.gpu_memory)
+    );
+    println!(
+        "    Load time: {:.1} ms (parse {:.1} ms, process {:.1} ms, upload {:.1} ms)",
+        duration_ms(scene.load_times.total()),
+        duration_ms(scene.load_times.parse),
+        duration_ms(scene.load_times.process),
+        duration_ms(scene.load_times.upload)
+    );
+    println!("Validation:");
+    for report in &scene.validation {
+        println!("  Model {}: {}", report.name, report.summary());
+    }
+}
+
+/// Print the numbered list of models with their visibility, marking the selected one
+pub fn print_model_list(scene: &Scene, selected: Option<usize>) {
+    println!("Models:");
+    for (i, (m, mesh)) in scene.models.iter().zip(&scene.meshes).enumerate() {
+        println!(
+            "{} {:>3} {}{}, {} triangles",
+            if selected == Some(i) { "*" } else { " " },
+            i,
+            m.name,
+            if mesh.visible { "" } else { " (hidden)" },
+            m.num_triangles
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use cgmath::{InnerSpace, Vector3};
+
+/// Compute a tangent for each vertex of an indexed triangle mesh, pointing along increasing
+/// texture u. The tangents of the triangles using a vertex are averaged and made orthogonal
+/// to its normal, and `w` holds the sign of the bitangent `cross(n, t) * w`, which points
+/// along increasing v. Vertices without a usable tangent get a zero vector.
+pub fn compute_tangents(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    texcoords: &[[f32; 2]],
+    indices: &[u32],
+) -> Vec<[f32; 4]> {
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    let mut tangents = vec![zero; positions.len()];
+    let mut bitangents = vec![zero; positions.len()];
+    for face in indices.chunks(3) {
+        if face.len() < 3 {
+            break;
+        }
+        let (a, b, c) = (face[0] as usize, face[1] as usize, face[2] as usize);
+        let e1 = Vector3::from(positions[b]) - Vector3::from(positions[a]);
+        let e2 = Vector3::from(positions[c]) - Vector3::from(positions[a]);
+        let (du1, dv1) = (
+            texcoords[b][0] - texcoords[a][0],
+            texcoords[b][1] - texcoords[a][1],
+        );
+        let (du2, dv2) = (
+            texcoords[c][0] - texcoords[a][0],
+            texcoords[c][1] - texcoords[a][1],
+        );
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < 1e-12 {
+            continue;
+        }
+        let t = (e1 * dv2 - e2 * dv1) / det;
+        let bt = (e2 * du1 - e1 * du2) / det;
+        for &v in &[a, b, c] {
+            tangents[v] += t;
+            bitangents[v] += bt;
+        }
+    }
+    tangents
+        .iter()
+        .zip(bitangents.iter())
+        .zip(normals.iter())
+        .map(|((&t, &bt), &n)| {
+            let n = Vector3::from(n);
+            let n = if n.magnitude2() > 0.0 {
+                n.normalize()
+            } else {
+                n
+            };
+            // Gram-Schmidt orthogonalize against the normal
+            let t = t - n * n.dot(t);
+            if t.magnitude2() < 1e-20 {
+                return [0.0; 4];
+            }
+            let t = t.normalize();
+            let w = if n.cross(t).dot(bt) < 0.0 { -1.0 } else { 1.0 };
+            [t.x, t.y, t.z, w]
+        })
+        .collect()
+}
@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use glium::backend::Facade;
+use glium::texture::{ClientFormat, MipmapsOption, RawImage2d, UncompressedFloatFormat};
+use glium::Texture2d;
+use image::{self, ColorType, DynamicImage, GenericImage};
+
+/// How an image is turned into a texture
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureKind {
+    /// Coverage mask for `map_d`, from the image's alpha channel if it has one and
+    /// otherwise its luminance
+    Mask,
+    /// Coverage mask from the alpha channel of a diffuse texture, if it isn't fully opaque
+    DiffuseAlpha,
+    /// Tangent space normal map, grayscale images are treated as bump maps and converted
+    /// to normals scaled by the `-bm` option
+    Normal,
+    /// RGB color
+    Color,
+    /// Single value from the image's luminance
+    Scalar,
+}
+
+/// Slope of the surface for a bump map step from black to white across one texel,
+/// before the `-bm` multiplier
+const BUMP_STRENGTH: f32 = 4.0;
+
+/// Loads the textures referenced by materials relative to the OBJ file, uploading each
+/// image once however many materials use it
+pub struct TextureLoader {
+    dir: PathBuf,
+    cache: HashMap<(String, TextureKind), Option<Rc<Texture2d>>>,
+    /// Estimated size in bytes of the loaded textures on the GPU
+    pub gpu_memory: usize,
+}
+
+impl TextureLoader {
+    pub fn new(obj_path: &Path) -> TextureLoader {
+        TextureLoader {
+            dir: obj_path.parent().map(Path::to_owned).unwrap_or_default(),
+            cache: HashMap::new(),
+            gpu_memory: 0,
+        }
+    }
+
+    /// Load the texture named in a material, returns None if the image has nothing to
+    /// use for this kind of texture. Images which fail to load are reported as a warning.
+    pub fn load<F: Facade>(
+        &mut self,
+        facade: &F,
+        name: &str,
+        kind: TextureKind,
+        warnings: &mut Vec<String>,
+    ) -> Option<Rc<Texture2d>> {
+        let key = (name.to_owned(), kind);
+        if let Some(texture) = self.cache.get(&key) {
+            return texture.clone();
+        }
+        let path = self.dir.join(texture_file(name));
+        let texture = match image::open(&path) {
+            Ok(img) => {
+                // Images are stored top row first, while texture coordinates start at the bottom
+                let img = img.flipv();
+                match kind {
+                    TextureKind::Mask if has_alpha(&img) => Some(alpha_channel(&img)),
+                    TextureKind::Mask => Some(img.to_luma().into_raw()),
+                    TextureKind::DiffuseAlpha if has_alpha(&img) => {
+                        let alpha = alpha_channel(&img);
+                        if alpha.iter().all(|&a| a == 255) {
+                            None
+                        } else {
+                            Some(alpha)
+                        }
+                    }
+                    TextureKind::DiffuseAlpha => None,
+                    TextureKind::Normal if is_gray(&img) => {
+                        let scale = texture_option(name, "-bm").unwrap_or(1.0);
+                        Some(bump_to_normals(&img, BUMP_STRENGTH * scale))
+                    }
+                    TextureKind::Normal | TextureKind::Color => Some(img.to_rgb().into_raw()),
+                    TextureKind::Scalar => Some(img.to_luma().into_raw()),
+                }
+                .map(|data| {
+                    let (width, height) = img.dimensions();
+                    let format = match kind {
+                        TextureKind::Normal | TextureKind::Color => ClientFormat::U8U8U8,
+                        _ => ClientFormat::U8,
+                    };
+                    self.gpu_memory += data.len() * 4 / 3;
+                    Rc::new(upload(facade, data, width, height, format))
+                })
+            }
+            Err(e) => {
+                warnings.push(format!("Failed to load texture {}: {}", path.display(), e));
+                None
+            }
+        };
+        self.cache.insert(key, texture.clone());
+        texture
+    }
+}
+
+/// Returns the file name of a texture statement's value, skipping any options before it
+/// and accepting Windows path separators
+fn texture_file(name: &str) -> String {
+    let file = if name.starts_with('-') {
+        name.split_whitespace().last().unwrap_or(name)
+    } else {
+        name
+    };
+    file.replace('\\', "/")
+}
+
+/// Returns the value of a numeric option given before the file name of a texture statement
+fn texture_option(name: &str, option: &str) -> Option<f32> {
+    let mut words = name.split_whitespace();
+    words.position(|w| w == option)?;
+    words.next().and_then(|v| f32::from_str(v).ok())
+}
+
+fn is_gray(img: &DynamicImage) -> bool {
+    matches!(img.color(), ColorType::Gray(_) | ColorType::GrayA(_))
+}
+
+fn has_alpha(img: &DynamicImage) -> bool {
+    matches!(img.color(), ColorType::GrayA(_) | ColorType::RGBA(_))
+}
+
+fn alpha_channel(img: &DynamicImage) -> Vec<u8> {
+    img.to_luma_alpha()
+        .into_raw()
+        .chunks(2)
+        .map(|p| p[1])
+        .collect()
+}
+
+/// Convert a height map to tangent space normals from its slopes along u and v, wrapping
+/// around at the edges. The image must already be flipped so rows go along increasing v.
+fn bump_to_normals(img: &DynamicImage, strength: f32) -> Vec<u8> {
+    let heights = img.to_luma();
+    let (width, height) = heights.dimensions();
+    let h = |x: u32, y: u32| f32::from(heights.get_pixel(x % width, y % height)[0]) / 255.0;
+    let mut normals = Vec::with_capacity((3 * width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let dx = 0.5 * (h(x + 1, y) - h(x + width - 1, y)) * strength;
+            let dy = 0.5 * (h(x, y + 1) - h(x, y + height - 1)) * strength;
+            let len = (dx * dx + dy * dy + 1.0).sqrt();
+            for &n in &[-dx / len, -dy / len, 1.0 / len] {
+                normals.push((255.0 * (0.5 * n + 0.5)).round() as u8);
+            }
+        }
+    }
+    normals
+}
+
+/// Upload an 8 bit image with mipmaps
+fn upload<F: Facade>(
+    facade: &F,
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    format: ClientFormat,
+) -> Texture2d {
+    let internal_format = match format {
+        ClientFormat::U8U8U8 => UncompressedFloatFormat::U8U8U8,
+        _ => UncompressedFloatFormat::U8,
+    };
+    let raw = RawImage2d {
+        data: Cow::Owned(data),
+        width,
+        height,
+        format,
+    };
+    Texture2d::with_format(
+        facade,
+        raw,
+        internal_format,
+        MipmapsOption::AutoGeneratedMipmaps,
+    )
+    .unwrap()
+}
@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use support::bounds::Bounds;
+use support::edges::{position_key, EdgeAdjacency};
+use support::json;
+use support::orientation::Orientation;
+use support::overlay::LineVertex;
+use support::stats;
+use support::tobj;
+
+/// Size of the crosses marking vertex issues relative to the model's bounding box diagonal
+const MARKER_SIZE: f32 = 0.01;
+/// Normals shorter than this are reported as zero length
+const MIN_NORMAL_LENGTH: f32 = 1e-6;
+
+/// The kinds of problems found when validating a mesh
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IssueKind {
+    NanPosition,
+    IndexOutOfRange,
+    DegenerateTriangle,
+    ZeroNormal,
+    DuplicateVertex,
+    NonManifoldEdge,
+    BoundaryEdge,
+}
+
+impl IssueKind {
+    pub const ALL: [IssueKind; 7] = [
+        IssueKind::NanPosition,
+        IssueKind::IndexOutOfRange,
+        IssueKind::DegenerateTriangle,
+        IssueKind::ZeroNormal,
+        IssueKind::DuplicateVertex,
+        IssueKind::NonManifoldEdge,
+        IssueKind::BoundaryEdge,
+    ];
+
+    /// The name of the issue in the JSON report
+    pub fn key(&self) -> &'static str {
+        match *self {
+            IssueKind::NanPosition => "nan_position",
+            IssueKind::IndexOutOfRange => "index_out_of_range",
+            IssueKind::DegenerateTriangle => "degenerate_triangle",
+            IssueKind::ZeroNormal => "zero_normal",
+            IssueKind::DuplicateVertex => "duplicate_vertex",
+            IssueKind::NonManifoldEdge => "non_manifold_edge",
+            IssueKind::BoundaryEdge => "boundary_edge",
+        }
+    }
+
+    /// Plural description of the issue for printing
+    pub fn label(&self) -> &'static str {
+        match *self {
+            IssueKind::NanPosition => "non-finite positions",
+            IssueKind::IndexOutOfRange => "out of range indices",
+            IssueKind::DegenerateTriangle => "degenerate triangles",
+            IssueKind::ZeroNormal => "zero length normals",
+            IssueKind::DuplicateVertex => "duplicate vertices",
+            IssueKind::NonManifoldEdge => "non-manifold edges",
+            IssueKind::BoundaryEdge => "boundary edges",
+        }
+    }
+
+    /// Color used to highlight the issue in the viewer
+    pub fn color(&self) -> [f32; 3] {
+        match *self {
+            IssueKind::NanPosition | IssueKind::IndexOutOfRange => [1.0, 0.0, 0.0],
+            IssueKind::DegenerateTriangle => [1.0, 0.0, 1.0],
+            IssueKind::ZeroNormal => [0.0, 1.0, 1.0],
+            IssueKind::DuplicateVertex => [1.0, 1.0, 0.0],
+            IssueKind::NonManifoldEdge => [1.0, 0.5, 0.0],
+            IssueKind::BoundaryEdge => [0.2, 0.6, 1.0],
+        }
+    }
+}
+
+/// The part of the mesh an issue was found in, vertices and triangles are indices into
+/// the `tobj::Mesh` data.
+#[derive(Copy, Clone, Debug)]
+pub enum Element {
+    Vertex(u32),
+    /// A vertex with the same position, normal and texture coordinate as an earlier one
+    Duplicate {
+        vertex: u32,
+        original: u32,
+    },
+    Triangle(usize),
+    Edge(u32, u32),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub element: Element,
+}
+
+/// The issues found in a single `tobj::Model`
+pub struct MeshReport {
+    pub name: String,
+    pub issues: Vec<Issue>,
+    /// Lines outlining the offending geometry, in the same space as the loaded positions
+    pub highlights: Vec<LineVertex>,
+}
+
+impl MeshReport {
+    pub fn count(&self, kind: IssueKind) -> usize {
+        self.issues.iter().filter(|i| i.kind == kind).count()
+    }
+
+    /// Returns a summary such as "2 degenerate triangles, 14 boundary edges" or "none"
+    pub fn summary(&self) -> String {
+        let counts: Vec<_> = IssueKind::ALL
+            .iter()
+            .map(|&k| (k, self.count(k)))
+            .filter(|&(_, n)| n > 0)
+            .map(|(k, n)| format!("{} {}", n, k.label()))
+            .collect();
+        if counts.is_empty() {
+            "none".to_owned()
+        } else {
+            counts.join(", ")
+        }
+    }
+}
+
+fn attribute(data: &[f32], n: usize, i: usize) -> Option<&[f32]> {
+    data.get(n * i..n * i + n)
+}
+
+/// Check the mesh for geometry which is likely to render incorrectly or cause problems
+/// for downstream tools. The edges are checked using the mesh's adjacency, which welds
+/// vertices by position so seams aren't reported as boundaries.
+pub fn check_mesh(
+    name: &str,
+    mesh: &tobj::Mesh,
+    adjacency: &EdgeAdjacency,
+    orientation: &Orientation,
+) -> MeshReport {
+    let num_vertices = mesh.positions.len() / 3;
+    let position = |i: u32| {
+        let i = i as usize;
+        orientation.apply([
+            mesh.positions[3 * i],
+            mesh.positions[3 * i + 1],
+            mesh.positions[3 * i + 2],
+        ])
+    };
+    let mut bounds = Bounds::empty();
+    for i in 0..num_vertices as u32 {
+        let p = position(i);
+        if p.iter().all(|x| x.is_finite()) {
+            bounds.extend(p);
+        }
+    }
+    let marker_size = if bounds.is_empty() {
+        0.0
+    } else {
+        MARKER_SIZE * bounds.diagonal()
+    };
+
+    let mut issues = Vec::new();
+    let mut highlights = Vec::new();
+    let mark_vertex = |highlights: &mut Vec<LineVertex>, p: [f32; 3], kind: IssueKind| {
+        for axis in 0..3 {
+            let mut a = p;
+            let mut b = p;
+            a[axis] -= marker_size;
+            b[axis] += marker_size;
+            push_line(highlights, a, b, kind);
+        }
+    };
+
+    let mut by_attributes = HashMap::new();
+    for i in 0..num_vertices as u32 {
+        let p = position(i);
+        if !p.iter().all(|x| x.is_finite()) {
+            issues.push(Issue {
+                kind: IssueKind::NanPosition,
+                element: Element::Vertex(i),
+            });
+        }
+        if let Some(n) = attribute(&mesh.normals, 3, i as usize) {
+            let len_sq = n[0] * n[0] + n[1] * n[1] + n[2] * n[2];
+            if !len_sq.is_finite() || len_sq < MIN_NORMAL_LENGTH * MIN_NORMAL_LENGTH {
+                issues.push(Issue {
+                    kind: IssueKind::ZeroNormal,
+                    element: Element::Vertex(i),
+                });
+                mark_vertex(&mut highlights, p, IssueKind::ZeroNormal);
+            }
+        }
+        let key = position_key(p);
+        let normal =
+            attribute(&mesh.normals, 3, i as usize).map(|n| position_key([n[0], n[1], n[2]]));
+        let texcoord = attribute(&mesh.texcoords, 2, i as usize)
+            .map(|t| [(t[0] + 0.0).to_bits(), (t[1] + 0.0).to_bits()]);
+        let original = *by_attributes.entry((key, normal, texcoord)).or_insert(i);
+        if original != i {
+            issues.push(Issue {
+                kind: IssueKind::DuplicateVertex,
+                element: Element::Duplicate {
+                    vertex: i,
+                    original,
+                },
+            });
+            mark_vertex(&mut highlights, p, IssueKind::DuplicateVertex);
+        }
+    }
+
+    for (t, face) in mesh.indices.chunks(3).enumerate() {
+        if face.len() < 3 || face.iter().any(|&i| i as usize >= num_vertices) {
+            issues.push(Issue {
+                kind: IssueKind::IndexOutOfRange,
+                element: Element::Triangle(t),
+            });
+            continue;
+        }
+        let (a, b, c) = (position(face[0]), position(face[1]), position(face[2]));
+        let longest_sq = [(a, b), (b, c), (c, a)]
+            .iter()
+            .map(|&(p, q)| {
+                let d = [q[0] - p[0], q[1] - p[1], q[2] - p[2]];
+                d[0] * d[0] + d[1] * d[1] + d[2] * d[2]
+            })
+            .fold(0.0, f32::max);
+        let area = stats::triangle_area(a, b, c);
+        if area.is_nan() || area <= f32::EPSILON * longest_sq {
+            issues.push(Issue {
+                kind: IssueKind::DegenerateTriangle,
+                element: Element::Triangle(t),
+            });
+            push_line(&mut highlights, a, b, IssueKind::DegenerateTriangle);
+            push_line(&mut highlights, b, c, IssueKind::DegenerateTriangle);
+            push_line(&mut highlights, c, a, IssueKind::DegenerateTriangle);
+        }
+    }
+
+    for e in adjacency.edges() {
+        let kind = if e.is_boundary() {
+            IssueKind::BoundaryEdge
+        } else if e.is_non_manifold() {
+            IssueKind::NonManifoldEdge
+        } else {
+            continue;
+        };
+        issues.push(Issue {
+            kind,
+            element: Element::Edge(e.a, e.b),
+        });
+        push_line(&mut highlights, position(e.a), position(e.b), kind);
+    }
+
+    MeshReport {
+        name: name.to_owned(),
+        issues,
+        highlights,
+    }
+}
+
+pub fn push_line(lines: &mut Vec<LineVertex>, a: [f32; 3], b: [f32; 3], kind: IssueKind) {
+    if a.iter().chain(b.iter()).all(|x| x.is_finite()) {
+        let color = kind.color();
+        lines.push(LineVertex { position: a, color });
+        lines.push(LineVertex { position: b, color });
+    }
+}
+
+/// Write the validation results for the file as JSON, listing the issue counts and
+/// offending elements of each model
+pub fn write_json(path: &Path, model_file: &Path, reports: &[MeshReport]) -> io::Result<()> {
+    let mut f = File::create(path)?;
+    writeln!(f, "{{")?;
+    writeln!(
+        f,
+        "  \"file\": {},",
+        json::string(&model_file.to_string_lossy())
+    )?;
+    writeln!(f, "  \"models\": [")?;
+    for (m, report) in reports.iter().enumerate() {
+        writeln!(f, "    {{")?;
+        writeln!(f, "      \"name\": {},", json::string(&report.name))?;
+        let counts: Vec<_> = IssueKind::ALL
+            .iter()
+            .map(|k| format!("\"{}\": {}", k.key(), report.count(*k)))
+            .collect();
+        writeln!(f, "      \"counts\": {{{}}},", counts.join(", "))?;
+        writeln!(f, "      \"issues\": [")?;
+        for (i, issue) in report.issues.iter().enumerate() {
+            let element = match issue.element {
+                Element::Vertex(v) => format!("\"vertex\": {}", v),
+                Element::Duplicate { vertex, original } => {
+                    format!("\"vertex\": {}, \"duplicate_of\": {}", vertex, original)
+                }
+                Element::Triangle(t) => format!("\"triangle\": {}", t),
+                Element::Edge(a, b) => format!("\"edge\": [{}, {}]", a, b),
+            };
+            let separator = if i + 1 < report.issues.len() { "," } else { "" };
+            writeln!(
+                f,
+                "        {{\"kind\": \"{}\", {}}}{}",
+                issue.kind.key(),
+                element,
+                separator
+            )?;
+        }
+        writeln!(f, "      ]")?;
+        let separator = if m + 1 < reports.len() { "," } else { "" };
+        writeln!(f, "    }}{}", separator)?;
+    }
+    writeln!(f, "  ]")?;
+    writeln!(f, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use support::edges::tests::cube;
+
+    fn flatten(points: &[[f32; 3]]) -> Vec<f32> {
+        points.iter().flat_map(|p| p.to_vec()).collect()
+    }
+
+    fn check(positions: Vec<f32>, indices: Vec<u32>) -> MeshReport {
+        let adjacency = EdgeAdjacency::new(&positions, &indices);
+        let mesh = tobj::Mesh::new(positions, Vec::new(), Vec::new(), indices, None);
+        check_mesh("test", &mesh, &adjacency, &Orientation::default())
+    }
+
+    fn triangles(report: &MeshReport, kind: IssueKind) -> Vec<usize> {
+        report
+            .issues
+            .iter()
+            .filter(|i| i.kind == kind)
+            .filter_map(|i| match i.element {
+                Element::Triangle(t) => Some(t),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn closed_cube_has_no_issues() {
+        let (positions, indices) = cube();
+        let report = check(positions, indices);
+        assert!(report.issues.is_empty(), "{}", report.summary());
+        assert!(report.highlights.is_empty());
+    }
+
+    #[test]
+    fn open_cube_has_boundary_edges() {
+        let (positions, mut indices) = cube();
+        indices.truncate(indices.len() - 6);
+        let report = check(positions, indices);
+        assert_eq!(report.count(IssueKind::BoundaryEdge), 4);
+        assert_eq!(report.issues.len(), 4);
+    }
+
+    #[test]
+    fn reports_degenerate_triangles_and_bad_indices() {
+        let positions = flatten(&[
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [2.0, 0.0, 0.0],
+        ]);
+        // A valid triangle, one along a line and one using a vertex which doesn't exist
+        let indices = vec![0, 1, 2, 0, 1, 3, 0, 2, 9];
+        let report = check(positions, indices);
+        assert_eq!(triangles(&report, IssueKind::DegenerateTriangle), vec![1]);
+        assert_eq!(triangles(&report, IssueKind::IndexOutOfRange), vec![2]);
+    }
+
+    #[test]
+    fn reports_duplicate_vertices() {
+        let positions = flatten(&[
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+        ]);
+        let report = check(positions, vec![0, 1, 2, 0, 3, 2]);
+        assert_eq!(report.count(IssueKind::DuplicateVertex), 1);
+    }
+}